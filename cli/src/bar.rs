@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use waysted_core::{
+    compositor::WindowInfo,
+    config::format_millis_short,
+    database::{Activity, AppAlias, AppScreentime},
+    ipc::{Status, Update},
+};
+
+/// Output of a waybar custom module.
+#[derive(Debug, Serialize)]
+pub struct BarOutput {
+    pub text: String,
+    pub tooltip: String,
+    pub class: String,
+    pub percentage: i32,
+}
+
+/// What the bar shows, kept up to date with the daemon updates.
+#[derive(Debug)]
+pub struct BarState {
+    window: Option<WindowInfo>,
+    activity: Activity,
+    paused: bool,
+    totals: Vec<AppScreentime>,
+
+    /// Aliases by lowercase app name, the totals are grouped by alias
+    aliases: HashMap<String, String>,
+}
+
+impl BarState {
+    pub fn new(status: Option<Status>, totals: Vec<AppScreentime>, apps: Vec<AppAlias>) -> Self {
+        let aliases = apps
+            .into_iter()
+            .filter_map(|app| Some((app.app_name.to_lowercase(), app.alias?)))
+            .collect();
+
+        match status {
+            Some(status) => Self {
+                window: status.window,
+                activity: status.activity,
+                paused: status.paused,
+                totals,
+                aliases,
+            },
+            None => Self {
+                window: None,
+                activity: Activity::Active,
+                paused: false,
+                totals,
+                aliases,
+            },
+        }
+    }
+
+    pub fn update(&mut self, update: Update) {
+        match update {
+            Update::Focus(window) => self.window = window,
+            Update::Activity(activity) => self.activity = activity,
+            Update::Paused(paused) => self.paused = paused,
+            Update::Totals(totals) => self.totals = totals,
+        }
+    }
+
+    /// Render the text with the format, replacing `{total}`, `{app}`, `{title}`, `{app_time}`
+    /// and `{activity}`. Waybar parses the output as Pango markup, so names and titles are
+    /// escaped.
+    pub fn render(&self, format: &str) -> BarOutput {
+        let total: u128 = self.totals.iter().map(|app| app.duration).sum();
+        // the focused app as it is reported in the totals
+        let app_name = self.window.as_ref().map_or("", |window| {
+            self.aliases
+                .get(&window.app_name.to_lowercase())
+                .unwrap_or(&window.app_name)
+        });
+        let focused_app = self.totals.iter().find(|app| app.app_name == app_name);
+
+        let text = replace_placeholders(format, |placeholder| {
+            Some(match placeholder {
+                "total" => format_millis_short(total),
+                "app" => escape_markup(app_name),
+                "title" => escape_markup(
+                    self.window
+                        .as_ref()
+                        .map_or("", |window| window.title.trim()),
+                ),
+                "app_time" => format_millis_short(focused_app.map_or(0, |app| app.duration)),
+                "activity" => self.activity.name().to_owned(),
+                _ => return None,
+            })
+        });
+
+        let tooltip = self
+            .totals
+            .iter()
+            .map(|app| {
+                let name = app.display_name.as_ref().unwrap_or(&app.app_name);
+                format!(
+                    "{}: {}",
+                    escape_markup(name),
+                    format_millis_short(app.duration)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let class = if self.paused {
+            "paused".to_owned()
+        } else {
+            self.activity.to_string()
+        };
+
+        BarOutput {
+            text,
+            tooltip,
+            class,
+            percentage: focused_app.map_or(0, |app| app.percentage),
+        }
+    }
+}
+
+/// Replace the `{placeholder}`s of `format` in a single pass, so replaced text is never
+/// expanded again. Unknown placeholders are kept as they are.
+fn replace_placeholders(format: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut text = String::with_capacity(format.len());
+    let mut rest = format;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let replaced = rest
+            .find('}')
+            .and_then(|end| Some((value(&rest[1..end])?, end)));
+        match replaced {
+            Some((value, end)) => {
+                text.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Escape the characters Pango markup gives a meaning to.
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(app_name: &str, minutes: u128, percentage: i32) -> AppScreentime {
+        AppScreentime {
+            id: 1,
+            app_name: app_name.to_owned(),
+            duration: minutes * 60 * 1000,
+            percentage,
+            display_name: None,
+            icon: None,
+            categories: Vec::new(),
+        }
+    }
+
+    fn state(title: &str, app_name: &str) -> BarState {
+        let status = Status {
+            window: Some(WindowInfo {
+                title: title.to_owned(),
+                app_name: app_name.to_owned(),
+                id: None,
+            }),
+            activity: Activity::Passive,
+            elapsed: 0,
+            paused: false,
+            resumes_in: None,
+        };
+        let apps = vec![AppAlias {
+            app_name: "org.mozilla.firefox".to_owned(),
+            alias: Some("firefox".to_owned()),
+        }];
+        BarState::new(
+            Some(status),
+            vec![app("firefox", 90, 75), app("foot", 30, 25)],
+            apps,
+        )
+    }
+
+    #[test]
+    fn replaces_each_placeholder() {
+        let output = state(" GitHub ", "org.mozilla.firefox")
+            .render("{total} {app} {title} {app_time} {activity} {unknown} {");
+
+        assert_eq!(
+            output.text,
+            "2h 00m firefox GitHub 1h 30m passive {unknown} {"
+        );
+        assert_eq!(output.tooltip, "firefox: 1h 30m\nfoot: 30m");
+        assert_eq!(output.class, "passive");
+        assert_eq!(output.percentage, 75);
+    }
+
+    #[test]
+    fn escapes_markup() {
+        let mut state = state("Q&A <draft>", "foot");
+        state.totals.push(app("a<b>", 1, 0));
+
+        let output = state.render("<b>{title}</b>");
+        assert_eq!(output.text, "<b>Q&amp;A &lt;draft&gt;</b>");
+        assert!(output.tooltip.ends_with("a&lt;b&gt;: 1m"));
+    }
+
+    #[test]
+    fn does_not_expand_placeholders_in_titles() {
+        let output = state("{app_time} {activity}", "foot").render("{title} {app}");
+        assert_eq!(output.text, "{app_time} {activity} foot");
+    }
+}
@@ -1,5 +1,10 @@
 use chrono::{DateTime, Local};
-use waysted_core::database::{AppGroup, AppScreentime, ScreenTimeInstance};
+use waysted_core::{
+    database::{
+        Activity, AppAlias, AppGroup, AppScreentime, CategoryScreentime, ScreenTimeInstance,
+    },
+    ipc::Status,
+};
 
 use crate::utils::format_millis;
 
@@ -31,6 +36,50 @@ impl DataOutput for Vec<AppScreentime> {
     }
 }
 
+impl DataOutput for Vec<AppAlias> {
+    fn to_string(&self, json: bool) -> String {
+        if json {
+            serde_json::to_string_pretty(self).unwrap()
+        } else {
+            let mut s = String::new();
+            for app in self {
+                match &app.alias {
+                    Some(alias) => s.push_str(&format!("{} -> {}\n", app.app_name, alias)),
+                    None => s.push_str(&format!("{}\n", app.app_name)),
+                }
+            }
+            s
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl DataOutput for Vec<CategoryScreentime> {
+    fn to_string(&self, json: bool) -> String {
+        if json {
+            serde_json::to_string_pretty(self).unwrap()
+        } else {
+            let mut s = String::new();
+            for category in self {
+                s.push_str(&format!(
+                    "{} ({}%): {}\n",
+                    category.category,
+                    category.percentage,
+                    format_millis(category.duration)
+                ));
+            }
+            s
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
 impl DataOutput for Vec<AppGroup> {
     fn to_string(&self, json: bool) -> String {
         if json {
@@ -67,8 +116,12 @@ impl DataOutput for Vec<ScreenTimeInstance> {
         } else {
             let mut s = String::new();
             for log in self {
+                let activity = match log.activity {
+                    Activity::Active => String::new(),
+                    activity => format!(" [{activity}]"),
+                };
                 s.push_str(&format!(
-                    "[{} - {}] {:<15} >> {} ({})\n",
+                    "[{} - {}] {:<15} >> {} ({}){}\n",
                     DateTime::from_timestamp_millis(log.start_timestamp)
                         .unwrap()
                         .with_timezone(&Local)
@@ -79,7 +132,8 @@ impl DataOutput for Vec<ScreenTimeInstance> {
                         .format("%Y-%m-%d %H:%M:%S%.f"),
                     log.app_name,
                     log.title,
-                    format_millis(log.duration)
+                    format_millis(log.duration),
+                    activity
                 ));
             }
             s
@@ -90,3 +144,38 @@ impl DataOutput for Vec<ScreenTimeInstance> {
         self.len()
     }
 }
+
+impl DataOutput for Status {
+    fn to_string(&self, json: bool) -> String {
+        if json {
+            return serde_json::to_string_pretty(self).unwrap();
+        }
+
+        let Some(window) = &self.window else {
+            return "No window is focused".to_owned();
+        };
+
+        let mut s = format!(
+            "{} >> {} ({})",
+            window.app_name,
+            window.title.trim(),
+            format_millis(self.elapsed)
+        );
+        if self.activity != Activity::Active {
+            s.push_str(&format!(" [{}]", self.activity));
+        }
+        match self.resumes_in {
+            Some(resumes_in) => s.push_str(&format!(
+                " [paused, resumes in {}]",
+                format_millis(resumes_in)
+            )),
+            None if self.paused => s.push_str(" [paused]"),
+            None => {}
+        }
+        s
+    }
+
+    fn size(&self) -> usize {
+        usize::from(self.window.is_some())
+    }
+}
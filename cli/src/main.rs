@@ -1,19 +1,33 @@
-use std::io;
+use std::{io, path::PathBuf, time::Duration};
 
-use chrono::{DateTime, Days, Local, NaiveDate, NaiveTime};
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveTime, Utc};
 use clap::{Parser, Subcommand};
 use pager::Pager;
 use regex::Regex;
-use waysted_core::database::Database;
+use waysted_core::{
+    config::{Config, parse_duration},
+    database::{Activity, AppScreentime, Database},
+    desktop::DesktopEntries,
+    ipc::{Client, Request, Response, Update},
+};
 
-use crate::{data_output::DataOutput, utils::format_bytes};
+use crate::{
+    bar::BarState,
+    data_output::DataOutput,
+    utils::{format_bytes, format_millis},
+};
 
+mod bar;
 mod data_output;
 mod utils;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
+    /// Path to the config file, defaults to `$XDG_CONFIG_HOME/waysted/config.toml`
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -37,8 +51,44 @@ enum Commands {
         /// Return raw screentime logs
         #[arg(long, group = "Mode")]
         logs: bool,
+
+        /// Group screentime by the categories from the config
+        #[arg(long, group = "Mode")]
+        categories: bool,
+
+        /// Only count the given activities: `active`, `passive` or `idle`, defaults to `active,passive`
+        #[arg(long = "activity", value_delimiter = ',')]
+        activities: Vec<Activity>,
+    },
+
+    /// Show the focused window and how long it has been counted for
+    Status {
+        /// Output as JSON
+        #[arg(short, long)]
+        json: bool,
+    },
+
+    /// Output today's screentime as JSON for a waybar custom module
+    Bar {
+        /// Text of the module, `{total}`, `{app}` (aliased), `{title}`, `{app_time}` and `{activity}` are replaced
+        #[arg(short, long, default_value = "{total}")]
+        format: String,
+
+        /// Keep printing a line whenever the daemon sends an update
+        #[arg(long)]
+        follow: bool,
+    },
+
+    /// Stop tracking screentime until resumed
+    Pause {
+        /// Resume by itself after a duration such as `30m` or `1h30m`
+        #[arg(long = "for", value_parser = parse_duration)]
+        duration: Option<Duration>,
     },
 
+    /// Resume tracking screentime after a pause
+    Resume,
+
     /// Clear collected screentime from database
     Clear {
         #[arg(short, long, value_parser = DateRange::parse_ymd_to_datetime)]
@@ -48,6 +98,12 @@ enum Commands {
         end: Option<DateTime<Local>>,
     },
 
+    /// Manage the recorded apps
+    Apps {
+        #[command(subcommand)]
+        command: AppsCommands,
+    },
+
     /// Get database metadata
     Db {
         #[command(subcommand)]
@@ -55,6 +111,28 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum AppsCommands {
+    /// List the recorded app names and their aliases
+    List {
+        /// Output as JSON
+        #[arg(short, long)]
+        json: bool,
+    },
+
+    /// Report the screentime of an app under another name, e.g. `waysted apps alias org.mozilla.firefox firefox`
+    Alias {
+        /// The app name as recorded, matched case insensitively
+        app_name: String,
+
+        /// The name to report it as
+        alias: String,
+    },
+
+    /// Remove the alias of an app
+    Unalias { app_name: String },
+}
+
 #[derive(Subcommand, Debug)]
 enum DbMetadataCommands {
     /// Get the path to the screentime db
@@ -132,24 +210,47 @@ impl DateRange {
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    let db = Database::new(false).map_err(|e| e.to_string())?;
+    let config = Config::load(cli.config.as_deref())?;
+    let db = Database::new(&config.database_path(), false).map_err(|e| e.to_string())?;
+    db.set_config_aliases(&config.aliases)?;
     match cli.command {
         Commands::Screentime {
             date_range,
             json,
             titles,
             logs,
+            categories,
+            activities,
         } => {
+            let activities = if activities.is_empty() {
+                Activity::DEFAULT.to_vec()
+            } else {
+                activities
+            };
+            let (start, end) = (date_range.start.to_utc(), date_range.end.to_utc());
+
             let data: Box<dyn DataOutput> = if titles {
-                Box::new(
-                    db.get_title_breakdown(date_range.start.to_utc(), date_range.end.to_utc())?,
-                )
+                Box::new(db.get_title_breakdown(start, end, &activities)?)
             } else if logs {
-                Box::new(db.get_logs(date_range.start.to_utc(), date_range.end.to_utc())?)
+                Box::new(db.get_logs(start, end, &activities)?)
+            } else if categories {
+                Box::new(db.get_category_screentime_in_range(
+                    start,
+                    end,
+                    &activities,
+                    &config.categories,
+                )?)
             } else {
-                Box::new(
-                    db.get_screentime_in_range(date_range.start.to_utc(), date_range.end.to_utc())?,
-                )
+                // the daemon includes the window that is currently focused
+                let screentime = match daemon_screentime(start, end, &activities) {
+                    Some(screentime) => screentime,
+                    None => {
+                        let mut screentime = db.get_screentime_in_range(start, end, &activities)?;
+                        DesktopEntries::load().annotate(&mut screentime);
+                        screentime
+                    }
+                };
+                Box::new(screentime)
             };
 
             if data.size() == 0 {
@@ -169,6 +270,72 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             println!("{}", output);
         }
+        Commands::Bar { format, follow } => {
+            let today = DateRange::parse_date_query("today")?;
+            let (start, end) = (today.start.to_utc(), today.end.to_utc());
+
+            if follow {
+                let updates = Client::connect()
+                    .and_then(|client| client.subscribe())
+                    .map_err(|err| {
+                        format!("Failed to subscribe to the daemon, is it running? {err}")
+                    })?;
+
+                let mut state = BarState::new(None, Vec::new(), db.get_apps()?);
+                let mut received_totals = false;
+                let mut last_output = String::new();
+                for update in updates {
+                    let update = update?;
+                    received_totals |= matches!(update, Update::Totals(_));
+                    state.update(update);
+
+                    // the current state is sent as several updates, the totals come last
+                    let output = serde_json::to_string(&state.render(&format))?;
+                    if received_totals && output != last_output {
+                        println!("{output}");
+                        last_output = output;
+                    }
+                }
+                return Ok(());
+            }
+
+            // without the daemon only the committed screentime is known
+            let state = match daemon_request(&Request::Status) {
+                Ok(Response::Status(status)) => BarState::new(
+                    Some(status),
+                    daemon_screentime(start, end, &Activity::DEFAULT).unwrap_or_default(),
+                    db.get_apps()?,
+                ),
+                _ => {
+                    let mut screentime =
+                        db.get_screentime_in_range(start, end, &Activity::DEFAULT)?;
+                    DesktopEntries::load().annotate(&mut screentime);
+                    BarState::new(None, screentime, db.get_apps()?)
+                }
+            };
+            println!("{}", serde_json::to_string(&state.render(&format))?);
+        }
+        Commands::Status { json } => match daemon_request(&Request::Status)? {
+            Response::Status(status) => println!("{}", status.to_string(json)),
+            response => return Err(format!("Unexpected response {response:?}").into()),
+        },
+        Commands::Pause { duration } => {
+            daemon_request(&Request::Pause {
+                duration: duration
+                    .map(|duration| u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)),
+            })?;
+            match duration {
+                Some(duration) => println!(
+                    "Tracking paused for {}.",
+                    format_millis(duration.as_millis())
+                ),
+                None => println!("Tracking paused, run `waysted resume` to resume."),
+            }
+        }
+        Commands::Resume => {
+            daemon_request(&Request::Resume)?;
+            println!("Tracking resumed.");
+        }
         Commands::Clear { start, end } => {
             print!("Are you sure you want to ");
             match (start, end) {
@@ -195,6 +362,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("Screentime was not cleared.");
             }
         }
+        Commands::Apps { command } => match command {
+            AppsCommands::List { json } => println!("{}", db.get_apps()?.to_string(json)),
+            AppsCommands::Alias { app_name, alias } => {
+                db.set_alias(&app_name, &alias)?;
+                println!("{app_name} is now reported as {alias}.");
+            }
+            AppsCommands::Unalias { app_name } => {
+                let removed = db.remove_alias(&app_name)?;
+                match db.get_alias(&app_name)? {
+                    Some(alias) => println!("{app_name} is reported as {alias} by the config."),
+                    None if removed => println!("{app_name} is no longer aliased."),
+                    None => println!("{app_name} has no alias."),
+                }
+            }
+        },
         Commands::Db { command } => match command {
             DbMetadataCommands::Path => println!("{}", db.get_path().display()),
             DbMetadataCommands::Size => println!("{}", format_bytes(db.get_size())),
@@ -203,3 +385,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Send a request to the daemon, error responses are turned into errors.
+fn daemon_request(request: &Request) -> Result<Response, Box<dyn std::error::Error>> {
+    let mut client = Client::connect()
+        .map_err(|err| format!("Failed to connect to the daemon, is it running? {err}"))?;
+    match client.request(request)? {
+        Response::Error(err) => Err(err.into()),
+        response => Ok(response),
+    }
+}
+
+/// Query the screentime from the daemon, None if the daemon is not running.
+fn daemon_screentime(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    activities: &[Activity],
+) -> Option<Vec<AppScreentime>> {
+    let mut client = Client::connect().ok()?;
+    let request = Request::Screentime {
+        start: start.timestamp_millis(),
+        end: end.timestamp_millis(),
+        activities: activities.to_vec(),
+    };
+    match client.request(&request).ok()? {
+        Response::Screentime(screentime) => Some(screentime),
+        _ => None,
+    }
+}
@@ -1,49 +1,301 @@
-use std::{env, io, sync::mpsc::Sender};
+use std::{env, fmt, io, str::FromStr, sync::mpsc::Sender};
 
-use log::info;
+use log::debug;
+use serde::{Deserialize, Serialize};
 
+mod cosmic;
+mod gnome;
 mod hyprland;
+mod kwin;
 mod niri;
+mod sway;
+mod toplevels;
+mod wlr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WindowInfo {
     pub title: String,
     pub app_name: String,
+    /// Id of the window in the compositor, for backends that can act on windows
+    #[serde(skip)]
+    pub id: Option<u64>,
 }
 
-pub trait Compositor {
+pub trait Compositor: Send {
     /// Retrieve the currently focused window.
     fn get_focused_window(&mut self) -> Result<WindowInfo, String>;
 
     /// Watch for changes in the focused window. Sends the window info over the channel.
-    /// This method will block the main thread and only return if the compositor IPC socket is closed.
+    /// This method will block the current thread and only return if the compositor IPC socket is closed,
+    /// in which case the caller is expected to reconnect.
     fn watch_focused_window(&mut self, sender: Sender<WindowInfo>) -> io::Result<()>;
+
+    /// Perform an action on the window with the given [`WindowInfo::id`], see
+    /// [`CompositorKind::supports`].
+    fn perform_action(&mut self, _id: u64, action: WindowAction) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("The compositor can't {action} windows"),
+        ))
+    }
+
+    /// A handle that makes [`Compositor::watch_focused_window`] return from another thread, even
+    /// if it is called before the watch started. Only provided by backends that clean up when
+    /// dropped, so they can be dropped on shutdown.
+    fn stop_handle(&self) -> Option<StopHandle> {
+        None
+    }
+}
+
+/// See [`Compositor::stop_handle`]
+pub type StopHandle = Box<dyn FnOnce() + Send>;
+
+/// Actions the compositor can be asked to perform on a window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowAction {
+    Close,
+    Minimize,
+}
+
+impl fmt::Display for WindowAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowAction::Close => f.write_str("close"),
+            WindowAction::Minimize => f.write_str("minimize"),
+        }
+    }
+}
+
+/// The compositor backends waysted can use to track the focused window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompositorKind {
+    Niri,
+    Hyprland,
+    Sway,
+    Cosmic,
+    Kde,
+    Gnome,
+    /// Any compositor implementing wlr-foreign-toplevel-management
+    Wlr,
 }
 
 const CURRENT_DESKTOP_ENV: &str = "XDG_CURRENT_DESKTOP";
 
-pub fn get_current_compositor() -> io::Result<Box<dyn Compositor>> {
-    let compositor_name = env::var(CURRENT_DESKTOP_ENV).map_err(|err| match err {
-        env::VarError::NotPresent => io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("No compositor found, {CURRENT_DESKTOP_ENV} is not set."),
-        ),
-        env::VarError::NotUnicode(_) => io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!("{CURRENT_DESKTOP_ENV} is not not valid unicode"),
-        ),
-    })?;
-
-    info!("{compositor_name} compositor found.");
-
-    match compositor_name.to_lowercase().as_str() {
-        "niri" => Ok(Box::new(niri::Niri::new()?)),
-        "hyprland" => Ok(Box::new(hyprland::Hyprland::new()?)),
-        unsupported => Err(io::Error::new(
-            io::ErrorKind::Unsupported,
-            format!(
-                "The {unsupported} compositor is currently unsupported, please file an issue or open a PR."
-            ),
-        )),
+/// Environment variables pointing to the IPC socket of a compositor
+const COMPOSITOR_SOCKET_ENVS: [(&str, CompositorKind); 3] = [
+    ("NIRI_SOCKET", CompositorKind::Niri),
+    ("HYPRLAND_INSTANCE_SIGNATURE", CompositorKind::Hyprland),
+    ("SWAYSOCK", CompositorKind::Sway),
+];
+
+/// Wayland globals that identify a toplevel protocol backend, in order of preference
+const TOPLEVEL_GLOBALS: [(&str, CompositorKind); 2] = [
+    ("zcosmic_toplevel_info_v1", CompositorKind::Cosmic),
+    ("zwlr_foreign_toplevel_manager_v1", CompositorKind::Wlr),
+];
+
+impl CompositorKind {
+    pub const ALL: [CompositorKind; 7] = [
+        CompositorKind::Niri,
+        CompositorKind::Hyprland,
+        CompositorKind::Sway,
+        CompositorKind::Cosmic,
+        CompositorKind::Kde,
+        CompositorKind::Gnome,
+        CompositorKind::Wlr,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompositorKind::Niri => "niri",
+            CompositorKind::Hyprland => "hyprland",
+            CompositorKind::Sway => "sway",
+            CompositorKind::Cosmic => "cosmic",
+            CompositorKind::Kde => "kde",
+            CompositorKind::Gnome => "gnome",
+            CompositorKind::Wlr => "wlr",
+        }
+    }
+
+    /// Whether the backend implements the action, checked before connecting since some backends
+    /// have side effects, e.g. loading a KWin script.
+    pub fn supports(&self, action: WindowAction) -> bool {
+        matches!(
+            (self, action),
+            (CompositorKind::Niri, WindowAction::Close)
+                | (CompositorKind::Hyprland, _)
+                | (CompositorKind::Sway, _)
+        )
+    }
+
+    /// Detect the running compositor.
+    ///
+    /// The IPC sockets are checked first since `XDG_CURRENT_DESKTOP` is often missing under
+    /// systemd or set to a list like `niri:GNOME`. If neither identify a compositor,
+    /// fall back to the toplevel protocols advertised by the wayland display.
+    pub fn detect() -> io::Result<Self> {
+        Self::detect_from(
+            |name| env::var_os(name).map(|value| value.to_string_lossy().into_owned()),
+            toplevels::advertised_globals,
+        )
+    }
+
+    /// [`CompositorKind::detect`] with the environment and the wayland globals looked up by
+    /// the given functions, the globals are only queried when the environment isn't enough.
+    fn detect_from(
+        env_var: impl Fn(&str) -> Option<String>,
+        advertised_globals: impl FnOnce() -> io::Result<Vec<String>>,
+    ) -> io::Result<Self> {
+        if let Some((name, kind)) = COMPOSITOR_SOCKET_ENVS
+            .iter()
+            .find(|(name, _)| env_var(name).is_some())
+        {
+            debug!("{name} is set");
+            return Ok(*kind);
+        }
+
+        if let Some(current_desktop) = env_var(CURRENT_DESKTOP_ENV) {
+            debug!("{CURRENT_DESKTOP_ENV} is {current_desktop}");
+            if let Some(kind) = current_desktop
+                .split(':')
+                .find_map(|desktop| desktop.parse::<CompositorKind>().ok())
+            {
+                return Ok(kind);
+            }
+        }
+
+        let globals = advertised_globals()?;
+        TOPLEVEL_GLOBALS
+            .iter()
+            .find(|(global, _)| globals.iter().any(|interface| interface == global))
+            .map(|(_, kind)| *kind)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "No supported compositor found, please file an issue or open a PR.",
+                )
+            })
+    }
+}
+
+impl fmt::Display for CompositorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CompositorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        CompositorKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = CompositorKind::ALL.iter().map(|kind| kind.name()).collect();
+                format!(
+                    "{s} is not a supported compositor, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Connect to the given compositor.
+pub fn get_compositor(kind: CompositorKind) -> io::Result<Box<dyn Compositor>> {
+    match kind {
+        CompositorKind::Niri => Ok(Box::new(niri::Niri::new()?)),
+        CompositorKind::Hyprland => Ok(Box::new(hyprland::Hyprland::new()?)),
+        CompositorKind::Sway => Ok(Box::new(sway::Sway::new()?)),
+        CompositorKind::Cosmic => Ok(Box::new(cosmic::Cosmic::new()?)),
+        CompositorKind::Kde => Ok(Box::new(kwin::KWin::new()?)),
+        CompositorKind::Gnome => Ok(Box::new(gnome::Gnome::new()?)),
+        CompositorKind::Wlr => Ok(Box::new(wlr::Wlr::new()?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(vars: &[(&str, &str)], globals: &[&str]) -> io::Result<CompositorKind> {
+        CompositorKind::detect_from(
+            |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            },
+            || Ok(globals.iter().map(|global| global.to_string()).collect()),
+        )
+    }
+
+    #[test]
+    fn parses_compositor_names() {
+        for kind in CompositorKind::ALL {
+            assert_eq!(kind.to_string().parse(), Ok(kind));
+            assert_eq!(kind.name().to_uppercase().parse(), Ok(kind));
+        }
+        assert!("kwin".parse::<CompositorKind>().is_err());
+        assert!("".parse::<CompositorKind>().is_err());
+    }
+
+    #[test]
+    fn deserializes_compositor_names() {
+        #[derive(Deserialize)]
+        struct Daemon {
+            compositor: CompositorKind,
+        }
+
+        let daemon: Daemon = toml::from_str(r#"compositor = "kde""#).unwrap();
+        assert_eq!(daemon.compositor, CompositorKind::Kde);
+        assert!(toml::from_str::<Daemon>(r#"compositor = "KDE""#).is_err());
+    }
+
+    #[test]
+    fn detects_sockets_before_the_desktop() {
+        let vars = [
+            ("XDG_CURRENT_DESKTOP", "GNOME"),
+            ("SWAYSOCK", "/tmp/sway.sock"),
+        ];
+        assert_eq!(detect(&vars, &[]).unwrap(), CompositorKind::Sway);
+
+        let vars = [
+            ("SWAYSOCK", "/tmp/sway.sock"),
+            ("NIRI_SOCKET", "/tmp/niri.sock"),
+        ];
+        assert_eq!(detect(&vars, &[]).unwrap(), CompositorKind::Niri);
+    }
+
+    #[test]
+    fn detects_the_first_known_desktop() {
+        let vars = [("XDG_CURRENT_DESKTOP", "ubuntu:GNOME")];
+        assert_eq!(detect(&vars, &[]).unwrap(), CompositorKind::Gnome);
+
+        let vars = [("XDG_CURRENT_DESKTOP", "KDE:sway")];
+        assert_eq!(detect(&vars, &[]).unwrap(), CompositorKind::Kde);
+    }
+
+    #[test]
+    fn falls_back_to_the_toplevel_globals() {
+        let vars = [("XDG_CURRENT_DESKTOP", "river")];
+        let globals = [
+            "wl_compositor",
+            "zwlr_foreign_toplevel_manager_v1",
+            "zcosmic_toplevel_info_v1",
+        ];
+        assert_eq!(detect(&vars, &globals).unwrap(), CompositorKind::Cosmic);
+        assert_eq!(detect(&vars, &globals[..2]).unwrap(), CompositorKind::Wlr);
+        assert!(detect(&vars, &["wl_compositor"]).is_err());
+    }
+
+    #[test]
+    fn only_queries_the_globals_when_needed() {
+        let kind = CompositorKind::detect_from(
+            |name| (name == "NIRI_SOCKET").then(|| "/tmp/niri.sock".to_owned()),
+            || panic!("the globals should not be queried"),
+        );
+        assert_eq!(kind.unwrap(), CompositorKind::Niri);
     }
 }
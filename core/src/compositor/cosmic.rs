@@ -0,0 +1,193 @@
+use std::{io, sync::mpsc::Sender};
+
+use super::{
+    Compositor, WindowInfo,
+    toplevels::{self, Toplevels},
+};
+use log::debug;
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, backend::ObjectId, event_created_child,
+};
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+    ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+    ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+};
+
+use self::protocol::{
+    zcosmic_toplevel_handle_v1::{self, ZcosmicToplevelHandleV1},
+    zcosmic_toplevel_info_v1::{self, ZcosmicToplevelInfoV1},
+};
+
+/// Bindings generated from the vendored cosmic-toplevel-info protocol.
+#[allow(clippy::all, dead_code, non_upper_case_globals, unused_imports)]
+mod protocol {
+    use wayland_client;
+    use wayland_client::protocol::*;
+    use wayland_protocols::ext::foreign_toplevel_list::v1::client::*;
+
+    pub mod __interfaces {
+        use wayland_client::backend as wayland_backend;
+        use wayland_client::protocol::__interfaces::*;
+        use wayland_protocols::ext::foreign_toplevel_list::v1::client::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/cosmic-toplevel-info-unstable-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/cosmic-toplevel-info-unstable-v1.xml");
+}
+
+/// Backend for COSMIC, the toplevels are listed through ext-foreign-toplevel-list and
+/// the activation state is provided by the cosmic-toplevel-info extension.
+pub struct Cosmic {
+    event_queue: EventQueue<Toplevels>,
+    toplevels: Toplevels,
+
+    // keep the globals alive so the compositor keeps sending toplevel events
+    _list: ExtForeignToplevelListV1,
+    _info: ZcosmicToplevelInfoV1,
+}
+
+impl Cosmic {
+    pub fn new() -> io::Result<Self> {
+        let (globals, mut event_queue) = toplevels::connect()?;
+        let qh = event_queue.handle();
+
+        // cosmic toplevel info needs to be bound first since it is used to extend every new toplevel
+        let info = globals
+            .bind::<ZcosmicToplevelInfoV1, _, _>(&qh, 2..=2, ())
+            .map_err(|err| io::Error::new(io::ErrorKind::Unsupported, err))?;
+        let list = globals
+            .bind::<ExtForeignToplevelListV1, _, _>(&qh, 1..=1, info.clone())
+            .map_err(|err| io::Error::new(io::ErrorKind::Unsupported, err))?;
+
+        let mut toplevels = Toplevels::default();
+        toplevels::sync(&mut event_queue, &mut toplevels)?;
+
+        Ok(Self {
+            event_queue,
+            toplevels,
+            _list: list,
+            _info: info,
+        })
+    }
+}
+
+impl Compositor for Cosmic {
+    fn get_focused_window(&mut self) -> Result<WindowInfo, String> {
+        self.event_queue
+            .roundtrip(&mut self.toplevels)
+            .map_err(|err| format!("Failure to communicate with COSMIC, {err}"))?;
+
+        self.toplevels
+            .focused_window()
+            .ok_or_else(|| "No toplevel is activated".to_owned())
+    }
+
+    fn watch_focused_window(&mut self, sender: Sender<WindowInfo>) -> io::Result<()> {
+        toplevels::watch(&mut self.event_queue, &mut self.toplevels, sender)
+    }
+}
+
+impl Dispatch<ExtForeignToplevelListV1, ZcosmicToplevelInfoV1> for Toplevels {
+    fn event(
+        state: &mut Self,
+        _list: &ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        info: &ZcosmicToplevelInfoV1,
+        _connection: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } => {
+                state.insert(toplevel.id());
+                // the extension object reports the state of the toplevel it was created for
+                info.get_cosmic_toplevel(&toplevel, qh, toplevel.id());
+            }
+            ext_foreign_toplevel_list_v1::Event::Finished => {
+                debug!("Foreign toplevel list finished");
+                state.finished = true;
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(Toplevels, ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for Toplevels {
+    fn event(
+        state: &mut Self,
+        handle: &ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let id = handle.id();
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::Done => state.commit(id),
+            ext_foreign_toplevel_handle_v1::Event::Closed => {
+                state.remove(&id);
+                handle.destroy();
+            }
+            ext_foreign_toplevel_handle_v1::Event::Title { title } => {
+                if let Some(toplevel) = state.pending_mut(&id) {
+                    toplevel.title = title;
+                }
+            }
+            ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                if let Some(toplevel) = state.pending_mut(&id) {
+                    toplevel.app_id = app_id;
+                }
+            }
+            _ => {
+                // ignore other events because they don't affect the focused window
+            }
+        }
+    }
+}
+
+impl Dispatch<ZcosmicToplevelInfoV1, ()> for Toplevels {
+    fn event(
+        _state: &mut Self,
+        _info: &ZcosmicToplevelInfoV1,
+        _event: zcosmic_toplevel_info_v1::Event,
+        _data: &(),
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // the toplevels are announced by the ext foreign toplevel list instead
+    }
+
+    event_created_child!(Toplevels, ZcosmicToplevelInfoV1, [
+        zcosmic_toplevel_info_v1::EVT_TOPLEVEL_OPCODE => (ZcosmicToplevelHandleV1, ObjectId::null())
+    ]);
+}
+
+/// The extension handles carry the id of the foreign toplevel handle they extend.
+impl Dispatch<ZcosmicToplevelHandleV1, ObjectId> for Toplevels {
+    fn event(
+        state: &mut Self,
+        handle: &ZcosmicToplevelHandleV1,
+        event: zcosmic_toplevel_handle_v1::Event,
+        toplevel_id: &ObjectId,
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zcosmic_toplevel_handle_v1::Event::State { state: states } => {
+                if let Some(toplevel) = state.pending_mut(toplevel_id) {
+                    let activated = zcosmic_toplevel_handle_v1::State::Activated as u32;
+                    toplevel.activated = toplevels::parse_states(&states).any(|s| s == activated);
+                }
+            }
+            zcosmic_toplevel_handle_v1::Event::Done => state.commit(toplevel_id.clone()),
+            zcosmic_toplevel_handle_v1::Event::Closed => handle.destroy(),
+            _ => {
+                // title and app id are tracked through the foreign toplevel handle
+            }
+        }
+    }
+}
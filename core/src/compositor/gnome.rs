@@ -0,0 +1,82 @@
+use std::{io, sync::mpsc::Sender};
+
+use super::{Compositor, WindowInfo};
+use log::{error, info};
+use zbus::{blocking::Connection, proxy};
+
+/// D-Bus interface exported by the bundled waysted GNOME Shell extension
+#[proxy(
+    interface = "io.github.myume.Waysted.Gnome",
+    default_service = "io.github.myume.Waysted.Gnome",
+    default_path = "/io/github/myume/Waysted/Gnome"
+)]
+trait Extension {
+    fn get_focused_window(&self) -> zbus::Result<(String, String)>;
+
+    #[zbus(signal)]
+    fn focus_changed(&self, wm_class: String, title: String) -> zbus::Result<()>;
+}
+
+/// Backend for GNOME Shell, which doesn't expose the focused window by itself.
+/// Requires the extension in `extensions/gnome` to be installed and enabled.
+pub struct Gnome {
+    extension: ExtensionProxyBlocking<'static>,
+}
+
+impl Gnome {
+    pub fn new() -> io::Result<Self> {
+        let connection = Connection::session().map_err(io::Error::other)?;
+        let extension = ExtensionProxyBlocking::new(&connection).map_err(io::Error::other)?;
+
+        // fail early if the extension is not running
+        extension.get_focused_window().map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("The waysted GNOME Shell extension is not enabled, {err}"),
+            )
+        })?;
+
+        Ok(Self { extension })
+    }
+}
+
+impl Compositor for Gnome {
+    fn get_focused_window(&mut self) -> Result<WindowInfo, String> {
+        match self.extension.get_focused_window() {
+            Ok((wm_class, _)) if wm_class.is_empty() => {
+                Err("No window has focus in GNOME Shell".to_owned())
+            }
+            Ok((wm_class, title)) => Ok(WindowInfo {
+                title,
+                app_name: wm_class,
+                id: None,
+            }),
+            Err(err) => Err(format!(
+                "Failure to communicate with the waysted GNOME Shell extension, {err}"
+            )),
+        }
+    }
+
+    fn watch_focused_window(&mut self, sender: Sender<WindowInfo>) -> io::Result<()> {
+        let focus_changes = self
+            .extension
+            .receive_focus_changed()
+            .map_err(io::Error::other)?;
+
+        for signal in focus_changes {
+            let args = signal.args().map_err(io::Error::other)?;
+            let window_info = WindowInfo {
+                title: args.title,
+                app_name: args.wm_class,
+                id: None,
+            };
+
+            if let Err(err) = sender.send(window_info) {
+                error!("Failed to send window info: {err}");
+            };
+        }
+
+        info!("The waysted GNOME Shell extension stopped sending focus changes");
+        Ok(())
+    }
+}
@@ -1,9 +1,10 @@
 use std::io;
 
-use crate::compositor::Compositor;
+use crate::compositor::{Compositor, WindowAction};
 use hyprland::data::Client;
+use hyprland::dispatch::{Dispatch, DispatchType};
 use hyprland::event_listener::EventListener;
-use hyprland::shared::HyprDataActiveOptional;
+use hyprland::shared::{Address, HyprDataActiveOptional};
 use log::error;
 
 pub struct Hyprland {}
@@ -20,6 +21,7 @@ impl Compositor for Hyprland {
             Ok(Some(client)) => Ok(super::WindowInfo {
                 title: client.title,
                 app_name: client.class.to_string(),
+                id: window_id(&client.address),
             }),
             Ok(None) => Err("No window has focus in Hyprland".to_owned()),
             Err(err) => Err(format!("Failed to get focused window from Hyprland: {err}")),
@@ -36,6 +38,7 @@ impl Compositor for Hyprland {
                 if let Err(err) = sender.send(super::WindowInfo {
                     title: window_event.title,
                     app_name: window_event.class.to_string(),
+                    id: window_id(&window_event.address),
                 }) {
                     error!("Failed to send window info: {err}");
                 };
@@ -45,7 +48,26 @@ impl Compositor for Hyprland {
             }
         });
 
-        event_listener.start_listener().unwrap();
-        Ok(())
+        event_listener.start_listener().map_err(io::Error::other)
     }
+
+    fn perform_action(&mut self, id: u64, action: WindowAction) -> io::Result<()> {
+        let window = format!("address:{id:#x}");
+        let (dispatcher, args) = match action {
+            WindowAction::Close => ("closewindow", window),
+            // hyprland has no minimized state, hide the window in a special workspace instead
+            WindowAction::Minimize => (
+                "movetoworkspacesilent",
+                format!("special:minimized,{window}"),
+            ),
+        };
+
+        Dispatch::call(DispatchType::Custom(dispatcher, &args)).map_err(io::Error::other)
+    }
+}
+
+/// Window addresses are formatted as hex numbers, e.g. `0x5f3a2c10`.
+fn window_id(address: &Address) -> Option<u64> {
+    let address = address.to_string();
+    u64::from_str_radix(address.trim_start_matches("0x"), 16).ok()
 }
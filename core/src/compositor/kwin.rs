@@ -0,0 +1,412 @@
+use std::{
+    env, fs, io,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, Sender, channel},
+    },
+    thread::spawn,
+};
+
+use super::{Compositor, StopHandle, WindowInfo};
+use log::{debug, error, info, warn};
+use zbus::{
+    blocking::{Connection, connection, fdo::DBusProxy},
+    interface,
+};
+
+/// Well known name and object the KWin script reports the active window to
+const SERVICE_NAME: &str = "io.github.myume.Waysted";
+const OBJECT_PATH: &str = "/io/github/myume/Waysted/KWin";
+
+const KWIN_SERVICE: &str = "org.kde.KWin";
+const KWIN_SCRIPTING_PATH: &str = "/Scripting";
+const KWIN_SCRIPTING_INTERFACE: &str = "org.kde.kwin.Scripting";
+
+const SCRIPT_NAME: &str = "waysted";
+const SCRIPT: &str = include_str!("kwin/waysted.js");
+
+#[derive(Default)]
+struct ActiveWindow {
+    window: Option<WindowInfo>,
+
+    /// Set while watching for focus changes
+    sender: Option<Sender<WindowInfo>>,
+}
+
+/// D-Bus object that receives the active window from the KWin script.
+struct ActiveWindowReceiver {
+    state: Arc<Mutex<ActiveWindow>>,
+}
+
+#[interface(name = "io.github.myume.Waysted.KWin")]
+impl ActiveWindowReceiver {
+    fn window_activated(&self, caption: String, resource_class: String) {
+        let window_info = WindowInfo {
+            title: caption,
+            app_name: resource_class,
+            id: None,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(sender) = &state.sender
+            && let Err(err) = sender.send(window_info.clone())
+        {
+            error!("Failed to send window info: {err}");
+        }
+        state.window = Some(window_info);
+    }
+}
+
+/// Backend for KWin, a small KWin script reports every window activation to an object
+/// exported on the session bus.
+pub struct KWin {
+    connection: Connection,
+    state: Arc<Mutex<ActiveWindow>>,
+
+    /// Ends the watch when KWin leaves the bus or the watch is stopped
+    watch_end: (Sender<()>, Receiver<()>),
+}
+
+impl KWin {
+    pub fn new() -> io::Result<Self> {
+        Self::connect(connection::Builder::session().map_err(io::Error::other)?)
+    }
+
+    /// Export the receiver on the bus of `builder` and load the script into KWin.
+    fn connect(builder: connection::Builder) -> io::Result<Self> {
+        let state = Arc::new(Mutex::new(ActiveWindow::default()));
+        let receiver = ActiveWindowReceiver {
+            state: state.clone(),
+        };
+
+        let connection = builder
+            .name(SERVICE_NAME)
+            .and_then(|builder| builder.serve_at(OBJECT_PATH, receiver))
+            .and_then(|builder| builder.build())
+            .map_err(io::Error::other)?;
+
+        let kwin = Self {
+            connection,
+            state,
+            watch_end: channel(),
+        };
+        kwin.load_script()?;
+
+        Ok(kwin)
+    }
+
+    /// Write the bundled script to disk and ask KWin to run it.
+    fn load_script(&self) -> io::Result<()> {
+        let script_dir = env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir);
+        let script_path = script_dir.join("waysted-kwin.js");
+        fs::write(&script_path, SCRIPT)?;
+
+        // a previous instance of the script may still be loaded if the daemon did not exit cleanly
+        self.unload_script()?;
+
+        let script_path = script_path.to_string_lossy();
+        let script_id: i32 = self
+            .call_scripting("loadScript", &(script_path.as_ref(), SCRIPT_NAME))?
+            .body()
+            .deserialize()
+            .map_err(io::Error::other)?;
+        if script_id < 0 {
+            return Err(io::Error::other(format!(
+                "KWin failed to load the script from {script_path}"
+            )));
+        }
+
+        self.call_scripting("start", &())?;
+        info!("Loaded the waysted KWin script");
+
+        Ok(())
+    }
+
+    fn unload_script(&self) -> io::Result<()> {
+        let unloaded: bool = self
+            .call_scripting("unloadScript", &(SCRIPT_NAME,))?
+            .body()
+            .deserialize()
+            .map_err(io::Error::other)?;
+        if unloaded {
+            debug!("Unloaded the waysted KWin script");
+        }
+
+        Ok(())
+    }
+
+    fn call_scripting<B>(&self, method: &str, body: &B) -> io::Result<zbus::Message>
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        self.connection
+            .call_method(
+                Some(KWIN_SERVICE),
+                KWIN_SCRIPTING_PATH,
+                Some(KWIN_SCRIPTING_INTERFACE),
+                method,
+                body,
+            )
+            .map_err(io::Error::other)
+    }
+}
+
+impl Drop for KWin {
+    fn drop(&mut self) {
+        if let Err(err) = self.unload_script() {
+            warn!("Failed to unload the waysted KWin script: {err}");
+        }
+    }
+}
+
+impl Compositor for KWin {
+    fn get_focused_window(&mut self) -> Result<WindowInfo, String> {
+        self.state
+            .lock()
+            .unwrap()
+            .window
+            .clone()
+            .ok_or_else(|| "No active window has been reported by KWin".to_owned())
+    }
+
+    /// The window activations are received on the connection's executor thread,
+    /// this only blocks until KWin leaves the session bus or the watch is stopped.
+    fn watch_focused_window(&mut self, sender: Sender<WindowInfo>) -> io::Result<()> {
+        self.state.lock().unwrap().sender = Some(sender);
+
+        let dbus = DBusProxy::new(&self.connection).map_err(io::Error::other)?;
+        let owner_changes = dbus
+            .receive_name_owner_changed_with_args(&[(0, KWIN_SERVICE)])
+            .map_err(io::Error::other)?;
+
+        let kwin_left = self.watch_end.0.clone();
+        spawn(move || {
+            for signal in owner_changes {
+                if signal.args().is_ok_and(|args| args.new_owner().is_none()) {
+                    info!("{KWIN_SERVICE} left the session bus");
+                    let _ = kwin_left.send(());
+                    break;
+                }
+            }
+        });
+        let _ = self.watch_end.1.recv();
+
+        self.state.lock().unwrap().sender = None;
+        Ok(())
+    }
+
+    fn stop_handle(&self) -> Option<StopHandle> {
+        let stop = self.watch_end.0.clone();
+        Some(Box::new(move || {
+            let _ = stop.send(());
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::mpsc::channel,
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// A `dbus-daemon` running a private session bus for one test.
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        /// Start the bus, or `None` if `dbus-daemon` is not installed.
+        fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--print-address", "--nofork"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+
+            Some(Self {
+                daemon,
+                address: address.trim().to_owned(),
+            })
+        }
+
+        fn builder(&self) -> connection::Builder<'static> {
+            connection::Builder::address(self.address.as_str()).unwrap()
+        }
+
+        fn connect(&self) -> Connection {
+            self.builder().build().unwrap()
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    /// Stands in for KWin's scripting object and records the calls made to it.
+    #[derive(Clone, Default)]
+    struct FakeScripting {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[interface(name = "org.kde.kwin.Scripting")]
+    impl FakeScripting {
+        #[zbus(name = "loadScript")]
+        fn load_script(&self, path: String, name: String) -> i32 {
+            assert_eq!(fs::read_to_string(path).unwrap(), SCRIPT);
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("loadScript {name}"));
+            0
+        }
+
+        #[zbus(name = "unloadScript")]
+        fn unload_script(&self, name: String) -> bool {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("unloadScript {name}"));
+            false
+        }
+
+        #[zbus(name = "start")]
+        fn start(&self) {
+            self.calls.lock().unwrap().push("start".to_owned());
+        }
+    }
+
+    impl FakeScripting {
+        /// Take the name of KWin on `bus`, it leaves the bus once the connection is dropped.
+        fn serve(&self, bus: &PrivateBus) -> Connection {
+            bus.builder()
+                .name(KWIN_SERVICE)
+                .and_then(|builder| builder.serve_at(KWIN_SCRIPTING_PATH, self.clone()))
+                .and_then(|builder| builder.build())
+                .unwrap()
+        }
+
+        fn take_calls(&self) -> Vec<String> {
+            std::mem::take(&mut self.calls.lock().unwrap())
+        }
+    }
+
+    /// Report a window activation the way the KWin script does.
+    fn activate(emitter: &Connection, caption: &str, resource_class: &str) {
+        emitter
+            .call_method(
+                Some(SERVICE_NAME),
+                OBJECT_PATH,
+                Some("io.github.myume.Waysted.KWin"),
+                "WindowActivated",
+                &(caption, resource_class),
+            )
+            .unwrap();
+    }
+
+    fn window(title: &str, app_name: &str) -> WindowInfo {
+        WindowInfo {
+            title: title.to_owned(),
+            app_name: app_name.to_owned(),
+            id: None,
+        }
+    }
+
+    #[test]
+    fn receives_windows_from_the_script() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon is not installed, skipping");
+            return;
+        };
+        let scripting = FakeScripting::default();
+        let kwin_connection = scripting.serve(&bus);
+
+        let mut kwin = KWin::connect(bus.builder()).unwrap();
+        assert_eq!(
+            scripting.take_calls(),
+            ["unloadScript waysted", "loadScript waysted", "start"]
+        );
+        assert!(kwin.get_focused_window().is_err());
+
+        let emitter = bus.connect();
+        activate(&emitter, "main.rs - Kate", "org.kde.kate");
+        assert_eq!(
+            kwin.get_focused_window(),
+            Ok(window("main.rs - Kate", "org.kde.kate"))
+        );
+
+        let (sender, receiver) = channel();
+        let watch = thread::spawn(move || {
+            let result = kwin.watch_focused_window(sender);
+            (kwin, result)
+        });
+
+        // activations are only forwarded once the watch has started
+        let forwarded = loop {
+            activate(&emitter, "~ : zsh", "org.kde.konsole");
+            if let Ok(window) = receiver.recv_timeout(Duration::from_millis(50)) {
+                break window;
+            }
+        };
+        assert_eq!(forwarded, window("~ : zsh", "org.kde.konsole"));
+
+        // KWin leaving the bus ends the watch, it comes back in case the watch wasn't
+        // subscribed to the name changes yet
+        drop(kwin_connection);
+        for _ in 0..100 {
+            if watch.is_finished() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+            drop(scripting.serve(&bus));
+        }
+        let (kwin, result) = watch.join().unwrap();
+        assert!(result.is_ok());
+
+        // dropping the old instance unloads its script before the next one loads it again
+        let _kwin_connection = scripting.serve(&bus);
+        drop(kwin);
+        assert_eq!(scripting.take_calls(), ["unloadScript waysted"]);
+
+        let mut kwin = KWin::connect(bus.builder()).unwrap();
+        assert_eq!(
+            scripting.take_calls(),
+            ["unloadScript waysted", "loadScript waysted", "start"]
+        );
+        activate(&emitter, "~ : zsh", "org.kde.konsole");
+        assert_eq!(
+            kwin.state.lock().unwrap().window,
+            Some(window("~ : zsh", "org.kde.konsole"))
+        );
+
+        // stopping the watch lets the instance be dropped on shutdown while KWin keeps running
+        let stop = kwin.stop_handle().unwrap();
+        let watch = thread::spawn(move || {
+            let result = kwin.watch_focused_window(channel().0);
+            (kwin, result)
+        });
+        stop();
+        let (kwin, result) = watch.join().unwrap();
+        assert!(result.is_ok());
+        drop(kwin);
+        assert_eq!(scripting.take_calls(), ["unloadScript waysted"]);
+    }
+}
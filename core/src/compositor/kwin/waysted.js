@@ -0,0 +1,36 @@
+// Reports the active window to the waysted daemon over D-Bus.
+// Loaded into KWin by waysted, see core/src/compositor/kwin.rs.
+
+const SERVICE = "io.github.myume.Waysted";
+const PATH = "/io/github/myume/Waysted/KWin";
+const INTERFACE = "io.github.myume.Waysted.KWin";
+
+let activeWindow = null;
+
+function report(window) {
+    if (!window) {
+        return;
+    }
+
+    callDBus(SERVICE, PATH, INTERFACE, "WindowActivated", window.caption, window.resourceClass);
+}
+
+function reportCaption() {
+    report(activeWindow);
+}
+
+function activate(window) {
+    if (activeWindow) {
+        activeWindow.captionChanged.disconnect(reportCaption);
+    }
+
+    activeWindow = window;
+    if (activeWindow) {
+        activeWindow.captionChanged.connect(reportCaption);
+    }
+
+    report(activeWindow);
+}
+
+workspace.windowActivated.connect(activate);
+activate(workspace.activeWindow);
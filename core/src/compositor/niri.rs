@@ -1,8 +1,8 @@
 use std::{collections::HashMap, io, sync::mpsc::Sender};
 
-use super::{Compositor, WindowInfo};
+use super::{Compositor, WindowAction, WindowInfo};
 use log::{debug, error, warn};
-use niri_ipc::{Event, Request, Response, Window, socket::Socket};
+use niri_ipc::{Action, Event, Request, Response, Window, socket::Socket};
 
 pub struct Niri {
     socket: Socket,
@@ -57,6 +57,7 @@ impl Niri {
                     let window_info = WindowInfo {
                         title: window.title.clone().unwrap_or_default(),
                         app_name: window.app_id.clone().unwrap_or_default(),
+                        id: Some(window.id),
                     };
                     if let Err(err) = sender.send(window_info) {
                         error!("Failed to send window info: {err}");
@@ -84,6 +85,7 @@ impl Niri {
                         let window_info = WindowInfo {
                             title: window.title.clone().unwrap_or_default(),
                             app_name: window.app_id.clone().unwrap_or_default(),
+                            id: Some(id),
                         };
 
                         if let Err(err) = sender.send(window_info) {
@@ -125,6 +127,7 @@ impl Compositor for Niri {
                 Ok(WindowInfo {
                     title: window.title.unwrap_or_default(),
                     app_name: window.app_id.unwrap_or_default(),
+                    id: Some(window.id),
                 })
             }
             // Unexpected reply
@@ -146,14 +149,47 @@ impl Compositor for Niri {
 
         let mut socket = Socket::connect()?;
 
-        let reply = socket.send(Request::EventStream)?;
-        if matches!(reply, Ok(Response::Handled)) {
-            let mut read_event = socket.read_events();
-            while let Ok(event) = read_event() {
-                self.handle_event(event, &sender);
+        match socket.send(Request::EventStream)? {
+            Ok(Response::Handled) => {}
+            Ok(response) => {
+                debug!("Unexpected reply {:?}", response);
+                return Err(io::Error::other("Unexpected reply from niri IPC socket"));
+            }
+            Err(message) => {
+                return Err(io::Error::other(format!(
+                    "Error message returned from niri: {message}"
+                )));
             }
         }
 
-        Ok(())
+        let mut read_event = socket.read_events();
+        loop {
+            let event = read_event()?;
+            self.handle_event(event, &sender);
+        }
+    }
+
+    fn perform_action(&mut self, id: u64, action: WindowAction) -> io::Result<()> {
+        let action = match action {
+            WindowAction::Close => Action::CloseWindow { id: Some(id) },
+            // niri has no minimized windows
+            WindowAction::Minimize => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "niri can't minimize windows",
+                ));
+            }
+        };
+
+        match self.socket.send(Request::Action(action))? {
+            Ok(Response::Handled) => Ok(()),
+            Ok(response) => {
+                debug!("Unexpected reply {:?}", response);
+                Err(io::Error::other("Unexpected reply from niri IPC socket"))
+            }
+            Err(message) => Err(io::Error::other(format!(
+                "Error message returned from niri: {message}"
+            ))),
+        }
     }
 }
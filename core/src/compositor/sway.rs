@@ -0,0 +1,436 @@
+use std::{
+    env,
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+};
+
+use super::{Compositor, WindowAction, WindowInfo};
+use log::{debug, error};
+use serde::{Deserialize, de::DeserializeOwned};
+
+const SWAY_SOCKET_ENV: &str = "SWAYSOCK";
+
+/// Magic string that prefixes every i3 IPC message.
+const IPC_MAGIC: &[u8; 6] = b"i3-ipc";
+
+const RUN_COMMAND: u32 = 0;
+const SUBSCRIBE: u32 = 2;
+const GET_TREE: u32 = 4;
+
+/// Events share the message type field with replies, but have the highest bit set.
+const EVENT_MASK: u32 = 1 << 31;
+const WINDOW_EVENT: u32 = EVENT_MASK | 3;
+
+#[derive(Debug, Deserialize)]
+struct WindowProperties {
+    class: Option<String>,
+}
+
+/// A node in the sway layout tree, only the fields needed to track focus are deserialized.
+#[derive(Debug, Deserialize)]
+struct Node {
+    id: u64,
+    name: Option<String>,
+    #[serde(default)]
+    focused: bool,
+
+    /// Set for native wayland windows
+    app_id: Option<String>,
+
+    /// Set for xwayland windows
+    window_properties: Option<WindowProperties>,
+
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+impl Node {
+    /// Containers and workspaces can also be focused, only views have an app id or window properties.
+    fn is_window(&self) -> bool {
+        self.app_id.is_some() || self.window_properties.is_some()
+    }
+
+    fn find_focused(&self) -> Option<&Node> {
+        if self.focused && self.is_window() {
+            return Some(self);
+        }
+
+        self.nodes
+            .iter()
+            .chain(self.floating_nodes.iter())
+            .find_map(|node| node.find_focused())
+    }
+
+    fn to_window_info(&self) -> WindowInfo {
+        let app_name = self
+            .app_id
+            .clone()
+            .or_else(|| {
+                self.window_properties
+                    .as_ref()
+                    .and_then(|properties| properties.class.clone())
+            })
+            .unwrap_or_default();
+
+        WindowInfo {
+            title: self.name.clone().unwrap_or_default(),
+            app_name,
+            id: Some(self.id),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WindowEvent {
+    change: String,
+    container: Node,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubscribeReply {
+    success: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandReply {
+    success: bool,
+    error: Option<String>,
+}
+
+pub struct Sway {
+    socket_path: PathBuf,
+    socket: UnixStream,
+}
+
+impl Sway {
+    pub fn new() -> io::Result<Self> {
+        let socket_path = env::var_os(SWAY_SOCKET_ENV).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{SWAY_SOCKET_ENV} is not set."),
+            )
+        })?;
+
+        Self::connect(Path::new(&socket_path))
+    }
+
+    fn connect(socket_path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            socket_path: socket_path.to_path_buf(),
+            socket: UnixStream::connect(socket_path)?,
+        })
+    }
+
+    fn handle_event(&self, event: WindowEvent, sender: &Sender<WindowInfo>) {
+        let title_changed = event.change == "title" && event.container.focused;
+        if event.change != "focus" && !title_changed {
+            // ignore other changes because they don't affect the focused window
+            return;
+        }
+
+        if !event.container.is_window() {
+            debug!("Focused container is not a window");
+            return;
+        }
+
+        if let Err(err) = sender.send(event.container.to_window_info()) {
+            error!("Failed to send window info: {err}");
+        };
+    }
+}
+
+/// Write a single i3 IPC message with the given type to the socket.
+fn send_message(socket: &mut UnixStream, message_type: u32, payload: &[u8]) -> io::Result<()> {
+    let mut message = Vec::with_capacity(IPC_MAGIC.len() + 8 + payload.len());
+    message.extend_from_slice(IPC_MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&message_type.to_ne_bytes());
+    message.extend_from_slice(payload);
+
+    socket.write_all(&message)
+}
+
+/// Read a single i3 IPC message from the socket, returning its type and payload.
+fn read_message(socket: &mut UnixStream) -> io::Result<(u32, Vec<u8>)> {
+    let mut header = [0; 14];
+    socket.read_exact(&mut header)?;
+
+    if &header[..IPC_MAGIC.len()] != IPC_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid magic string in sway IPC message",
+        ));
+    }
+
+    let length = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+    let message_type = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+
+    let mut payload = vec![0; length as usize];
+    socket.read_exact(&mut payload)?;
+
+    Ok((message_type, payload))
+}
+
+/// Send a request and wait for its reply, skipping any events that arrive in between.
+fn request<T: DeserializeOwned>(
+    socket: &mut UnixStream,
+    message_type: u32,
+    payload: &[u8],
+) -> io::Result<T> {
+    send_message(socket, message_type, payload)?;
+
+    loop {
+        let (reply_type, reply) = read_message(socket)?;
+        if reply_type == message_type {
+            return serde_json::from_slice(&reply).map_err(io::Error::other);
+        }
+
+        debug!("Skipping unexpected sway IPC message of type {reply_type:#x}");
+    }
+}
+
+impl Compositor for Sway {
+    fn get_focused_window(&mut self) -> Result<WindowInfo, String> {
+        let tree: Node = request(&mut self.socket, GET_TREE, &[])
+            .map_err(|err| format!("Failure to communicate with sway, {err}"))?;
+
+        tree.find_focused()
+            .map(Node::to_window_info)
+            .ok_or_else(|| "No window has focus in sway".to_owned())
+    }
+
+    /// Subscribe to sway window events and send the window info on focus and title changes
+    fn watch_focused_window(&mut self, sender: Sender<WindowInfo>) -> io::Result<()> {
+        let mut socket = UnixStream::connect(&self.socket_path)?;
+
+        let reply: SubscribeReply = request(&mut socket, SUBSCRIBE, br#"["window"]"#)?;
+        if !reply.success {
+            return Err(io::Error::other(
+                "Failed to subscribe to sway window events",
+            ));
+        }
+
+        loop {
+            let (message_type, payload) = match read_message(&mut socket) {
+                Ok(message) => message,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            if message_type != WINDOW_EVENT {
+                continue;
+            }
+
+            match serde_json::from_slice::<WindowEvent>(&payload) {
+                Ok(event) => self.handle_event(event, &sender),
+                Err(err) => error!("Failed to parse sway window event: {err}"),
+            }
+        }
+    }
+
+    fn perform_action(&mut self, id: u64, action: WindowAction) -> io::Result<()> {
+        let command = match action {
+            WindowAction::Close => "kill",
+            WindowAction::Minimize => "move scratchpad",
+        };
+        let command = format!("[con_id={id}] {command}");
+
+        let replies: Vec<CommandReply> =
+            request(&mut self.socket, RUN_COMMAND, command.as_bytes())?;
+        match replies.into_iter().find(|reply| !reply.success) {
+            Some(reply) => Err(io::Error::other(format!(
+                "sway failed to {action} the window: {}",
+                reply.error.unwrap_or_default()
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::net::UnixListener, process, sync::mpsc::channel, thread};
+
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    /// Listener of a fake sway IPC socket, removed once dropped.
+    struct FakeSway {
+        path: PathBuf,
+        listener: Option<UnixListener>,
+    }
+
+    impl FakeSway {
+        fn bind(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("waysted-{}-{name}.sock", process::id()));
+            let _ = fs::remove_file(&path);
+            let listener = UnixListener::bind(&path).unwrap();
+            Self {
+                path,
+                listener: Some(listener),
+            }
+        }
+    }
+
+    impl Drop for FakeSway {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn reply(socket: &mut UnixStream, message_type: u32, payload: Value) {
+        send_message(socket, message_type, payload.to_string().as_bytes()).unwrap();
+    }
+
+    fn window(title: &str, app_name: &str, id: u64) -> WindowInfo {
+        WindowInfo {
+            title: title.to_owned(),
+            app_name: app_name.to_owned(),
+            id: Some(id),
+        }
+    }
+
+    #[test]
+    fn finds_the_focused_window_in_the_tree() {
+        let mut sway = FakeSway::bind("tree");
+        let listener = sway.listener.take().unwrap();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let (message_type, _) = read_message(&mut socket).unwrap();
+            assert_eq!(message_type, GET_TREE);
+
+            // events arriving before the reply are skipped
+            reply(
+                &mut socket,
+                WINDOW_EVENT,
+                json!({"change": "close", "container": {"id": 9}}),
+            );
+            reply(
+                &mut socket,
+                GET_TREE,
+                json!({
+                    "id": 1,
+                    "name": "root",
+                    "nodes": [{
+                        "id": 2,
+                        "name": "1",
+                        "nodes": [{
+                            "id": 3,
+                            "nodes": [{"id": 4, "name": "vim", "app_id": "foot"}]
+                        }],
+                        "floating_nodes": [{
+                            "id": 5,
+                            "name": "xterm",
+                            "focused": true,
+                            "window_properties": {"class": "XTerm"}
+                        }]
+                    }]
+                }),
+            );
+        });
+
+        let mut compositor = Sway::connect(&sway.path).unwrap();
+        let focused = compositor.get_focused_window();
+        server.join().unwrap();
+        assert_eq!(focused, Ok(window("xterm", "XTerm", 5)));
+    }
+
+    #[test]
+    fn focused_workspace_is_not_a_window() {
+        let mut sway = FakeSway::bind("workspace");
+        let listener = sway.listener.take().unwrap();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            read_message(&mut socket).unwrap();
+            reply(
+                &mut socket,
+                GET_TREE,
+                json!({"id": 1, "nodes": [{"id": 2, "name": "1", "focused": true}]}),
+            );
+        });
+
+        let mut compositor = Sway::connect(&sway.path).unwrap();
+        let focused = compositor.get_focused_window();
+        server.join().unwrap();
+        assert!(focused.is_err());
+    }
+
+    #[test]
+    fn sends_focus_and_title_changes() {
+        let mut sway = FakeSway::bind("events");
+        let listener = sway.listener.take().unwrap();
+        let server = thread::spawn(move || {
+            // the connection for requests stays unused
+            let (_requests, _) = listener.accept().unwrap();
+            let (mut socket, _) = listener.accept().unwrap();
+            let (message_type, payload) = read_message(&mut socket).unwrap();
+            assert_eq!(message_type, SUBSCRIBE);
+            assert_eq!(payload, br#"["window"]"#);
+            reply(&mut socket, SUBSCRIBE, json!({"success": true}));
+
+            let events = [
+                json!({"change": "focus", "container": {"id": 4, "name": "vim", "focused": true, "app_id": "foot"}}),
+                json!({"change": "title", "container": {"id": 4, "name": "vim foo", "focused": true, "app_id": "foot"}}),
+                // title changes of unfocused windows and other changes are ignored
+                json!({"change": "title", "container": {"id": 6, "name": "bg", "app_id": "a"}}),
+                json!({"change": "move", "container": {"id": 4, "name": "vim foo", "focused": true, "app_id": "foot"}}),
+                // focused containers without a window are skipped
+                json!({"change": "focus", "container": {"id": 3, "focused": true}}),
+                json!({"change": "focus", "container": {"id": 5, "name": "xterm", "focused": true, "window_properties": {"class": "XTerm"}}}),
+            ];
+            for event in events {
+                reply(&mut socket, WINDOW_EVENT, event);
+            }
+            // closing the socket ends the watch
+        });
+
+        let mut compositor = Sway::connect(&sway.path).unwrap();
+        let (sender, receiver) = channel();
+        compositor.watch_focused_window(sender).unwrap();
+        server.join().unwrap();
+
+        let windows: Vec<WindowInfo> = receiver.try_iter().collect();
+        assert_eq!(
+            windows,
+            [
+                window("vim", "foot", 4),
+                window("vim foo", "foot", 4),
+                window("xterm", "XTerm", 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_commands_on_the_window() {
+        let mut sway = FakeSway::bind("command");
+        let listener = sway.listener.take().unwrap();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let (message_type, payload) = read_message(&mut socket).unwrap();
+            assert_eq!(message_type, RUN_COMMAND);
+            reply(&mut socket, RUN_COMMAND, json!([{"success": true}]));
+            let (_, failing) = read_message(&mut socket).unwrap();
+            reply(
+                &mut socket,
+                RUN_COMMAND,
+                json!([{"success": false, "error": "No matching node."}]),
+            );
+            (payload, failing)
+        });
+
+        let mut compositor = Sway::connect(&sway.path).unwrap();
+        assert!(compositor.perform_action(4, WindowAction::Close).is_ok());
+        assert!(
+            compositor
+                .perform_action(9, WindowAction::Minimize)
+                .is_err()
+        );
+        let (payload, failing) = server.join().unwrap();
+        assert_eq!(payload, b"[con_id=4] kill");
+        assert_eq!(failing, b"[con_id=9] move scratchpad");
+    }
+}
@@ -0,0 +1,162 @@
+//! Toplevel bookkeeping shared by the backends built on wayland toplevel protocols.
+
+use std::{collections::HashMap, io, sync::mpsc::Sender};
+
+use super::WindowInfo;
+use log::error;
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+    backend::ObjectId,
+    globals::{GlobalList, GlobalListContents, registry_queue_init},
+    protocol::wl_registry::WlRegistry,
+};
+
+#[derive(Debug, Default, Clone)]
+pub(super) struct Toplevel {
+    pub title: String,
+    pub app_id: String,
+    pub activated: bool,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Toplevels {
+    /// Toplevel state is double buffered, changes are pending until the done event.
+    pending: HashMap<ObjectId, Toplevel>,
+    current: HashMap<ObjectId, Toplevel>,
+    focused: Option<ObjectId>,
+
+    /// Set while watching for focus changes
+    pub sender: Option<Sender<WindowInfo>>,
+
+    /// Set when the compositor stops sending toplevel events
+    pub finished: bool,
+}
+
+impl Toplevels {
+    pub fn insert(&mut self, id: ObjectId) {
+        self.pending.insert(id, Toplevel::default());
+    }
+
+    pub fn pending_mut(&mut self, id: &ObjectId) -> Option<&mut Toplevel> {
+        let toplevel = self.pending.get_mut(id);
+        if toplevel.is_none() {
+            error!("Toplevel could not be found: {id}");
+        }
+        toplevel
+    }
+
+    pub fn focused_window(&self) -> Option<WindowInfo> {
+        let toplevel = self.current.get(self.focused.as_ref()?)?;
+        Some(WindowInfo {
+            title: toplevel.title.clone(),
+            app_name: toplevel.app_id.clone(),
+            id: None,
+        })
+    }
+
+    /// Apply the pending state of the toplevel and notify the sender if the focused window changed.
+    pub fn commit(&mut self, id: ObjectId) {
+        let Some(toplevel) = self.pending.get(&id).cloned() else {
+            error!("Toplevel could not be found: {id}");
+            return;
+        };
+
+        let previous = self.current.insert(id.clone(), toplevel.clone());
+        let was_focused = self.focused.as_ref() == Some(&id);
+
+        if toplevel.activated {
+            let title_changed = previous.is_some_and(|previous| previous.title != toplevel.title);
+            self.focused = Some(id);
+
+            if !was_focused || title_changed {
+                self.notify_focus_change();
+            }
+        } else if was_focused {
+            self.focused = None;
+        }
+    }
+
+    pub fn remove(&mut self, id: &ObjectId) {
+        self.pending.remove(id);
+        self.current.remove(id);
+        if self.focused.as_ref() == Some(id) {
+            self.focused = None;
+        }
+    }
+
+    fn notify_focus_change(&self) {
+        if let Some(sender) = &self.sender
+            && let Some(window_info) = self.focused_window()
+            && let Err(err) = sender.send(window_info)
+        {
+            error!("Failed to send window info: {err}");
+        }
+    }
+}
+
+/// Connect to the wayland display and retrieve the advertised globals.
+pub(super) fn connect() -> io::Result<(GlobalList, EventQueue<Toplevels>)> {
+    let connection = Connection::connect_to_env().map_err(io::Error::other)?;
+    registry_queue_init::<Toplevels>(&connection).map_err(io::Error::other)
+}
+
+/// List the interfaces of the globals advertised by the wayland display.
+pub(super) fn advertised_globals() -> io::Result<Vec<String>> {
+    let (globals, _) = connect()?;
+    Ok(globals
+        .contents()
+        .clone_list()
+        .into_iter()
+        .map(|global| global.interface)
+        .collect())
+}
+
+/// Roundtrip until the initial toplevels and their state have been received.
+pub(super) fn sync(
+    event_queue: &mut EventQueue<Toplevels>,
+    toplevels: &mut Toplevels,
+) -> io::Result<()> {
+    // The first roundtrip announces the toplevels, the second one receives their state.
+    for _ in 0..2 {
+        event_queue.roundtrip(toplevels).map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+/// Dispatch events until the compositor stops sending toplevel events.
+pub(super) fn watch(
+    event_queue: &mut EventQueue<Toplevels>,
+    toplevels: &mut Toplevels,
+    sender: Sender<WindowInfo>,
+) -> io::Result<()> {
+    toplevels.sender = Some(sender);
+
+    while !toplevels.finished {
+        event_queue
+            .blocking_dispatch(toplevels)
+            .map_err(io::Error::other)?;
+    }
+
+    toplevels.sender = None;
+    Ok(())
+}
+
+/// Parse the array of native endian u32 values used by the toplevel state events.
+pub(super) fn parse_states(states: &[u8]) -> impl Iterator<Item = u32> + '_ {
+    states
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Toplevels {
+    fn event(
+        _state: &mut Self,
+        _registry: &WlRegistry,
+        _event: <WlRegistry as Proxy>::Event,
+        _data: &GlobalListContents,
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // globals are only needed once at startup
+    }
+}
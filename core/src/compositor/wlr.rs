@@ -0,0 +1,122 @@
+use std::{io, sync::mpsc::Sender};
+
+use super::{
+    Compositor, WindowInfo,
+    toplevels::{self, Toplevels},
+};
+use log::debug;
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle, event_created_child};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+/// Backend for wlroots based compositors (river, labwc, wayfire, ...) using the
+/// wlr-foreign-toplevel-management protocol.
+pub struct Wlr {
+    event_queue: EventQueue<Toplevels>,
+    toplevels: Toplevels,
+
+    // keep the manager alive so the compositor keeps sending toplevel events
+    _manager: ZwlrForeignToplevelManagerV1,
+}
+
+impl Wlr {
+    pub fn new() -> io::Result<Self> {
+        let (globals, mut event_queue) = toplevels::connect()?;
+
+        let manager = globals
+            .bind::<ZwlrForeignToplevelManagerV1, _, _>(&event_queue.handle(), 1..=3, ())
+            .map_err(|err| io::Error::new(io::ErrorKind::Unsupported, err))?;
+
+        let mut toplevels = Toplevels::default();
+        toplevels::sync(&mut event_queue, &mut toplevels)?;
+
+        Ok(Self {
+            event_queue,
+            toplevels,
+            _manager: manager,
+        })
+    }
+}
+
+impl Compositor for Wlr {
+    fn get_focused_window(&mut self) -> Result<WindowInfo, String> {
+        self.event_queue
+            .roundtrip(&mut self.toplevels)
+            .map_err(|err| format!("Failure to communicate with the compositor, {err}"))?;
+
+        self.toplevels
+            .focused_window()
+            .ok_or_else(|| "No toplevel is activated".to_owned())
+    }
+
+    fn watch_focused_window(&mut self, sender: Sender<WindowInfo>) -> io::Result<()> {
+        toplevels::watch(&mut self.event_queue, &mut self.toplevels, sender)
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for Toplevels {
+    fn event(
+        state: &mut Self,
+        _manager: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _data: &(),
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state.insert(toplevel.id());
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                debug!("Foreign toplevel manager finished");
+                state.finished = true;
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(Toplevels, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for Toplevels {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let id = handle.id();
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Done => state.commit(id),
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                state.remove(&id);
+                handle.destroy();
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
+                if let Some(toplevel) = state.pending_mut(&id) {
+                    toplevel.title = title;
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                if let Some(toplevel) = state.pending_mut(&id) {
+                    toplevel.app_id = app_id;
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: states } => {
+                if let Some(toplevel) = state.pending_mut(&id) {
+                    let activated = zwlr_foreign_toplevel_handle_v1::State::Activated as u32;
+                    toplevel.activated = toplevels::parse_states(&states).any(|s| s == activated);
+                }
+            }
+            _ => {
+                // ignore other events because they don't affect the focused window
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Configuration shared by the daemon and the cli, read from `$XDG_CONFIG_HOME/waysted/config.toml`.
+
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::info;
+use serde::Deserialize;
+
+use crate::{
+    compositor::CompositorKind,
+    database::Database,
+    limits::Limit,
+    rules::{CategoryRule, IgnoreRule, PrivacyRule},
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Path to the database, defaults to `$XDG_DATA_HOME/waysted/waysted.db`
+    database: Option<PathBuf>,
+
+    pub daemon: DaemonConfig,
+
+    /// Windows that are not recorded as they are, the first matching rule applies
+    pub ignore: Vec<IgnoreRule>,
+
+    /// What is stored of the window titles per app, the first matching rule applies
+    pub privacy: Vec<PrivacyRule>,
+
+    /// Names to report apps as, in addition to the aliases set with `waysted apps alias`
+    pub aliases: HashMap<String, String>,
+
+    /// Categories of apps for reports, the first matching rule applies
+    #[serde(rename = "category")]
+    pub categories: Vec<CategoryRule>,
+
+    /// Daily screentime limits of apps and categories
+    #[serde(rename = "limit")]
+    pub limits: Vec<Limit>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// The compositor to track instead of detecting it
+    pub compositor: Option<CompositorKind>,
+
+    /// Seconds without input after which the user is considered idle, 0 disables idle detection
+    pub idle_timeout: u64,
+
+    /// Seconds between saving the time of the focused window
+    pub checkpoint_interval: u64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            compositor: None,
+            idle_timeout: 300,
+            checkpoint_interval: 60,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config from `path`, or from the default location if it exists.
+    pub fn load(path: Option<&Path>) -> io::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = Config::default_path();
+                if !path.exists() {
+                    return Ok(Config::default());
+                }
+                path
+            }
+        };
+
+        let config = fs::read_to_string(&path)?;
+        let config = toml::from_str(&config).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid config {}, {err}", path.display()),
+            )
+        })?;
+        info!("Config loaded from {}", path.display());
+
+        Ok(config)
+    }
+
+    pub fn default_path() -> PathBuf {
+        let config_dir = env::var("XDG_CONFIG_HOME")
+            .or_else(|_| env::var("HOME").map(|home| format!("{home}/.config")))
+            .unwrap_or_default();
+        PathBuf::from(config_dir)
+            .join("waysted")
+            .join("config.toml")
+    }
+
+    pub fn database_path(&self) -> PathBuf {
+        match &self.database {
+            Some(path) => expand_home(path),
+            None => Database::default_path(),
+        }
+    }
+}
+
+/// Expand a leading `~` to the home directory.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(path), Some(home)) => PathBuf::from(home).join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Parse a duration such as `30m`, `1h30m` or `90s`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("{s} is not a duration, expected e.g. `30m`, `1h30m` or `90s`");
+
+    let mut seconds: u64 = 0;
+    let mut digits = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let value: u64 = digits.parse().map_err(|_| invalid())?;
+        digits.clear();
+        let unit = match c {
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        seconds = value
+            .checked_mul(unit)
+            .and_then(|value| seconds.checked_add(value))
+            .ok_or_else(invalid)?;
+    }
+
+    if !digits.is_empty() || seconds == 0 {
+        return Err(invalid());
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Format a duration in ms compactly, e.g. `1h 05m` or `12m`.
+pub fn format_millis_short(millis: u128) -> String {
+    let minutes = millis / 1000 / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, 0) => "<1m".to_owned(),
+        (0, minutes) => format!("{minutes}m"),
+        (hours, minutes) => format!("{hours}h {minutes:02}m"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration(" 1h30m "), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_duration("1h1m1s"), Ok(Duration::from_secs(3661)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for s in ["", "0m", "30", "m", "1d", "1h 30m", "-5m", "1.5h"] {
+            assert!(parse_duration(s).is_err(), "{s:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        assert!(parse_duration("9999999999999999h").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+        assert!(parse_duration(&format!("{}s1s", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn formats_durations_in_minutes() {
+        assert_eq!(format_millis_short(59_999), "<1m");
+        assert_eq!(format_millis_short(12 * 60_000 + 59_999), "12m");
+        assert_eq!(format_millis_short(65 * 60_000), "1h 05m");
+        assert_eq!(format_millis_short(120 * 60_000), "2h 00m");
+    }
+}
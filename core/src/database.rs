@@ -1,17 +1,25 @@
 use std::{
+    cmp::Reverse,
     collections::HashMap,
-    env, fs, io,
+    env, fmt, fs, io,
     os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
+    str::FromStr,
     time::Duration,
 };
 
 use chrono::{DateTime, Utc};
 use log::info;
-use rusqlite::Connection;
+use rusqlite::{
+    Connection, ToSql,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+};
 use serde::{Deserialize, Serialize};
 
-use crate::compositor::WindowInfo;
+use crate::{
+    compositor::WindowInfo,
+    rules::{self, CategoryRule},
+};
 
 pub struct Database {
     connection: Connection,
@@ -19,7 +27,76 @@ pub struct Database {
     db_path: Box<Path>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// What the user was doing while a window was focused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Activity {
+    /// Using the focused window
+    Active,
+    /// No input while an idle inhibitor was active, e.g. watching a video
+    Passive,
+    /// Away from the computer
+    Idle,
+}
+
+impl Activity {
+    pub const ALL: [Activity; 3] = [Activity::Active, Activity::Passive, Activity::Idle];
+
+    /// Counted as screentime unless other activities are requested
+    pub const DEFAULT: [Activity; 2] = [Activity::Active, Activity::Passive];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Activity::Active => "active",
+            Activity::Passive => "passive",
+            Activity::Idle => "idle",
+        }
+    }
+}
+
+impl fmt::Display for Activity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Activity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        Activity::ALL
+            .into_iter()
+            .find(|activity| activity.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Activity::ALL
+                    .iter()
+                    .map(|activity| activity.name())
+                    .collect();
+                format!(
+                    "{s} is not an activity, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl ToSql for Activity {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.name().into())
+    }
+}
+
+impl FromSql for Activity {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|err: String| FromSqlError::Other(err.into()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppScreentime {
     pub id: i32,
     pub app_name: String,
@@ -27,6 +104,29 @@ pub struct AppScreentime {
     /// duration in ms
     pub duration: u128,
     pub percentage: i32,
+
+    /// From the desktop entry of the app, see [`crate::desktop::DesktopEntries::annotate`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppAlias {
+    pub app_name: String,
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CategoryScreentime {
+    pub category: String,
+
+    /// duration in ms
+    pub duration: u128,
+    pub percentage: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,6 +139,7 @@ pub struct ScreenTimeInstance {
     pub duration: u128,
     pub start_timestamp: i64,
     pub end_timestamp: i64,
+    pub activity: Activity,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,26 +159,22 @@ pub struct AppGroup {
 }
 
 impl Database {
-    pub fn new(create_new: bool) -> Result<Self, Box<dyn std::error::Error>> {
-        let data_dir = env::var("XDG_DATA_HOME")
-            .or_else(|_| env::var("HOME").map(|home| format!("{home}/.local/share")))
-            .unwrap_or_default();
-        let db_dir = PathBuf::from(data_dir).join("waysted");
-        let db_file = db_dir.join("waysted.db");
+    pub fn new(db_file: &Path, create_new: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        if !db_file.exists() {
+            if !create_new {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Waysted Database not found at {}.", db_file.display()),
+                )));
+            }
 
-        if !db_dir.exists() && create_new {
             info!("Waysted db not found, creating new db.");
-            fs::create_dir(db_dir)?;
-        }
-
-        if !db_file.exists() && !create_new {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::NotFound,
-                "Waysted Database not found.",
-            )));
+            if let Some(db_dir) = db_file.parent() {
+                fs::create_dir_all(db_dir)?;
+            }
         }
 
-        let connection = Connection::open(&db_file)?;
+        let connection = Connection::open(db_file)?;
 
         info!("Database loaded from {}", db_file.display());
 
@@ -88,7 +185,49 @@ impl Database {
                 app_name TEXT NOT NULL,
                 duration INTEGER NOT NULL,
                 start_timestamp NOT NULL,
-                end_timestamp NOT NULL
+                end_timestamp NOT NULL,
+                activity TEXT NOT NULL DEFAULT 'active'
+            )",
+            (),
+        )?;
+
+        // databases created before the activity was tracked only contain active time
+        let has_activity: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('screentime') WHERE name = 'activity'",
+            (),
+            |row| row.get(0),
+        )?;
+        if !has_activity {
+            info!("Adding the activity column to the screentime table.");
+            connection.execute(
+                "ALTER TABLE screentime ADD COLUMN activity TEXT NOT NULL DEFAULT 'active'",
+                (),
+            )?;
+        }
+
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS pauses (
+                id INTEGER PRIMARY KEY,
+                start_timestamp INTEGER NOT NULL,
+                end_timestamp INTEGER
+            )",
+            (),
+        )?;
+
+        // app names are aliased when querying, so the same app reported under different names
+        // is grouped together
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS aliases (
+                app_name TEXT PRIMARY KEY COLLATE NOCASE,
+                alias TEXT NOT NULL
+            )",
+            (),
+        )?;
+        // aliases from the config only live as long as the connection
+        connection.execute(
+            "CREATE TEMP TABLE IF NOT EXISTS config_aliases (
+                app_name TEXT PRIMARY KEY COLLATE NOCASE,
+                alias TEXT NOT NULL
             )",
             (),
         )?;
@@ -99,41 +238,96 @@ impl Database {
         })
     }
 
+    /// Location of the database unless configured otherwise.
+    pub fn default_path() -> PathBuf {
+        let data_dir = env::var("XDG_DATA_HOME")
+            .or_else(|_| env::var("HOME").map(|home| format!("{home}/.local/share")))
+            .unwrap_or_default();
+        PathBuf::from(data_dir).join("waysted").join("waysted.db")
+    }
+
+    /// Insert a row for the focused window and return its id.
     pub fn log_focus_duration(
         &self,
         window_info: WindowInfo,
         duration: Duration,
         start_timestamp: DateTime<Utc>,
         end_timestamp: DateTime<Utc>,
-    ) {
+        activity: Activity,
+    ) -> i64 {
         self.connection
             .execute(
-                "INSERT INTO screentime (title, app_name, duration, start_timestamp, end_timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
+                "INSERT INTO screentime (title, app_name, duration, start_timestamp, end_timestamp, activity) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
                 (
                     &window_info.title,
                     &window_info.app_name,
                     duration.as_millis() as i64,
                     // store timestamps in epoch millis so it's easily comparable
                     start_timestamp.timestamp_millis(),
-                    end_timestamp.timestamp_millis()
+                    end_timestamp.timestamp_millis(),
+                    activity,
+                ),
+            )
+            .unwrap();
+        self.connection.last_insert_rowid()
+    }
+
+    /// Extend a row logged with [`Database::log_focus_duration`] while the window is still focused.
+    pub fn update_focus_duration(&self, id: i64, duration: Duration, end_timestamp: DateTime<Utc>) {
+        self.connection
+            .execute(
+                "UPDATE screentime SET duration = ?2, end_timestamp = ?3 WHERE id = ?1",
+                (
+                    id,
+                    duration.as_millis() as i64,
+                    end_timestamp.timestamp_millis(),
                 ),
             )
             .unwrap();
     }
 
+    /// Delete a row logged with [`Database::log_focus_duration`], for spans too short to keep.
+    pub fn remove_focus_duration(&self, id: i64) {
+        self.connection
+            .execute("DELETE FROM screentime WHERE id = ?1", (id,))
+            .unwrap();
+    }
+
+    /// Record the start of a pause in tracking and return its id, the end is unknown until resumed.
+    pub fn log_pause(&self, start_timestamp: DateTime<Utc>) -> i64 {
+        self.connection
+            .execute(
+                "INSERT INTO pauses (start_timestamp) VALUES (?1)",
+                (start_timestamp.timestamp_millis(),),
+            )
+            .unwrap();
+        self.connection.last_insert_rowid()
+    }
+
+    pub fn end_pause(&self, id: i64, end_timestamp: DateTime<Utc>) {
+        self.connection
+            .execute(
+                "UPDATE pauses SET end_timestamp = ?2 WHERE id = ?1",
+                (id, end_timestamp.timestamp_millis()),
+            )
+            .unwrap();
+    }
+
     pub fn get_screentime_in_range(
         &self,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
+        activities: &[Activity],
     ) -> Result<Vec<AppScreentime>, rusqlite::Error> {
-        let mut stmt = self.connection.prepare(
-            "SELECT id, app_name, SUM(duration) AS duration,
-             CAST(ROUND(CAST(SUM(duration) AS REAL) / CAST((SELECT SUM(duration) FROM screentime 
-             WHERE ?1 <= start_timestamp AND start_timestamp <= ?2) AS real) * 100.0) AS INTEGER) FROM screentime 
-             WHERE ?1 <= start_timestamp AND start_timestamp <= ?2
-             GROUP BY app_name
+        let activity_filter = activity_filter(activities);
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT id, {ALIASED_APP_NAME} AS app, SUM(duration) AS duration,
+             IFNULL(CAST(ROUND(CAST(SUM(duration) AS REAL) / CAST((SELECT SUM(duration) FROM screentime 
+             WHERE ?1 <= start_timestamp AND start_timestamp <= ?2 AND {activity_filter}) AS real) * 100.0) AS INTEGER), 0) FROM screentime 
+             WHERE ?1 <= start_timestamp AND start_timestamp <= ?2 AND {activity_filter}
+             GROUP BY app
              ORDER BY duration DESC",
-        )?;
+        ))?;
 
         stmt.query_map([start.timestamp_millis(), end.timestamp_millis()], |row| {
             Ok(AppScreentime {
@@ -141,21 +335,67 @@ impl Database {
                 app_name: row.get(1)?,
                 duration: row.get::<usize, i64>(2)? as u128,
                 percentage: row.get(3)?,
+                display_name: None,
+                icon: None,
+                categories: Vec::new(),
             })
         })?
         .collect()
     }
 
+    /// Screentime per category, windows are categorized by their app name and title.
+    pub fn get_category_screentime_in_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        activities: &[Activity],
+        categories: &[CategoryRule],
+    ) -> Result<Vec<CategoryScreentime>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT {ALIASED_APP_NAME} AS app, title, SUM(duration) FROM screentime
+             WHERE ?1 <= start_timestamp AND start_timestamp <= ?2 AND duration > 0 AND {}
+             GROUP BY app, title",
+            activity_filter(activities)
+        ))?;
+
+        let mut durations: HashMap<&str, u128> = HashMap::new();
+        let mut rows = stmt.query([start.timestamp_millis(), end.timestamp_millis()])?;
+        while let Some(row) = rows.next()? {
+            let app_name: String = row.get(0)?;
+            let title: String = row.get(1)?;
+            let duration = row.get::<usize, i64>(2)? as u128;
+            *durations
+                .entry(rules::categorize(categories, &app_name, &title))
+                .or_default() += duration;
+        }
+
+        let total: u128 = durations.values().sum();
+        let mut data: Vec<CategoryScreentime> = durations
+            .into_iter()
+            .map(|(category, duration)| CategoryScreentime {
+                category: category.to_owned(),
+                duration,
+                percentage: (duration as f64 / total as f64 * 100.0).round() as i32,
+            })
+            .collect();
+
+        data.sort_by_key(|category| Reverse(category.duration));
+
+        Ok(data)
+    }
+
     pub fn get_logs(
         &self,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
+        activities: &[Activity],
     ) -> Result<Vec<ScreenTimeInstance>, rusqlite::Error> {
-        let mut stmt = self.connection.prepare(
-            "SELECT * FROM screentime
-             WHERE ?1 <= start_timestamp AND start_timestamp <= ?2
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT id, title, app_name, duration, start_timestamp, end_timestamp, activity FROM screentime
+             WHERE ?1 <= start_timestamp AND start_timestamp <= ?2 AND duration > 0 AND {}
              ORDER BY start_timestamp DESC",
-        )?;
+            activity_filter(activities)
+        ))?;
 
         stmt.query_map([start.timestamp_millis(), end.timestamp_millis()], |row| {
             Ok(ScreenTimeInstance {
@@ -165,6 +405,7 @@ impl Database {
                 duration: row.get::<usize, i64>(3)? as u128,
                 start_timestamp: row.get(4)?,
                 end_timestamp: row.get(5)?,
+                activity: row.get(6)?,
             })
         })?
         .collect()
@@ -174,14 +415,16 @@ impl Database {
         &self,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
+        activities: &[Activity],
     ) -> Result<Vec<AppGroup>, rusqlite::Error> {
-        let mut stmt = self.connection.prepare(
-            "SELECT title, app_name, SUM(duration) AS duration
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT title, {ALIASED_APP_NAME} AS app, SUM(duration) AS duration
              FROM screentime
-             WHERE ?1 <= start_timestamp AND start_timestamp <= ?2 and duration > 0
-             GROUP BY app_name, title
+             WHERE ?1 <= start_timestamp AND start_timestamp <= ?2 and duration > 0 AND {}
+             GROUP BY app, title
              ORDER BY duration DESC",
-        )?;
+            activity_filter(activities)
+        ))?;
 
         let titles: Result<Vec<TitleInstance>, rusqlite::Error> = stmt
             .query_map([start.timestamp_millis(), end.timestamp_millis()], |row| {
@@ -203,9 +446,9 @@ impl Database {
             }
         }
 
-        app_groups
-            .iter_mut()
-            .for_each(|(_, instances)| instances.sort_by(|a, b| b.duration.cmp(&a.duration)));
+        app_groups.iter_mut().for_each(|(_, instances)| {
+            instances.sort_by_key(|instance| Reverse(instance.duration))
+        });
 
         let mut data: Vec<AppGroup> = app_groups
             .into_iter()
@@ -216,7 +459,7 @@ impl Database {
             })
             .collect();
 
-        data.sort_by(|a, b| b.duration.cmp(&a.duration));
+        data.sort_by_key(|group| Reverse(group.duration));
 
         Ok(data)
     }
@@ -248,6 +491,80 @@ impl Database {
         }
     }
 
+    /// Report the time of `app_name` as `alias`, app names are matched case insensitively.
+    pub fn set_alias(&self, app_name: &str, alias: &str) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "INSERT INTO aliases (app_name, alias) VALUES (?1, ?2)
+             ON CONFLICT (app_name) DO UPDATE SET alias = ?2",
+            (app_name, alias),
+        )?;
+        Ok(())
+    }
+
+    /// Remove the alias of `app_name`, returns false if it had none.
+    pub fn remove_alias(&self, app_name: &str) -> Result<bool, rusqlite::Error> {
+        let removed = self
+            .connection
+            .execute("DELETE FROM aliases WHERE app_name = ?1", (app_name,))?;
+        Ok(removed > 0)
+    }
+
+    /// Replace the aliases from the config, aliases set with [`Database::set_alias`] take
+    /// precedence.
+    pub fn set_config_aliases(
+        &self,
+        aliases: &HashMap<String, String>,
+    ) -> Result<(), rusqlite::Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute("DELETE FROM config_aliases", ())?;
+        for (app_name, alias) in aliases {
+            transaction.execute(
+                "INSERT OR REPLACE INTO config_aliases (app_name, alias) VALUES (?1, ?2)",
+                (app_name, alias),
+            )?;
+        }
+        transaction.commit()
+    }
+
+    /// The alias of `app_name`, if it has one.
+    pub fn get_alias(&self, app_name: &str) -> Result<Option<String>, rusqlite::Error> {
+        self.connection.query_row(
+            "SELECT COALESCE(
+                (SELECT alias FROM aliases WHERE app_name = ?1),
+                (SELECT alias FROM config_aliases WHERE app_name = ?1)
+             )",
+            (app_name,),
+            |row| row.get(0),
+        )
+    }
+
+    /// Every recorded or aliased app name with its alias.
+    pub fn get_apps(&self) -> Result<Vec<AppAlias>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare(
+            "SELECT apps.app_name, COALESCE(aliases.alias, config_aliases.alias) FROM (
+                SELECT DISTINCT app_name FROM screentime
+                UNION
+                SELECT app_name FROM aliases
+                WHERE app_name NOT IN (SELECT app_name FROM screentime)
+                UNION
+                SELECT app_name FROM config_aliases
+                WHERE app_name NOT IN (SELECT app_name FROM screentime)
+                    AND app_name NOT IN (SELECT app_name FROM aliases)
+             ) AS apps
+             LEFT JOIN aliases ON aliases.app_name = apps.app_name
+             LEFT JOIN config_aliases ON config_aliases.app_name = apps.app_name
+             ORDER BY apps.app_name COLLATE NOCASE",
+        )?;
+
+        stmt.query_map((), |row| {
+            Ok(AppAlias {
+                app_name: row.get(0)?,
+                alias: row.get(1)?,
+            })
+        })?
+        .collect()
+    }
+
     pub fn get_path(&self) -> PathBuf {
         self.db_path.to_path_buf()
     }
@@ -256,3 +573,250 @@ impl Database {
         fs::metadata(&self.db_path).unwrap().size()
     }
 }
+
+/// SQL expression of the app name of a screentime row with its alias applied.
+const ALIASED_APP_NAME: &str = "COALESCE(
+    (SELECT alias FROM aliases WHERE aliases.app_name = screentime.app_name),
+    (SELECT alias FROM config_aliases WHERE config_aliases.app_name = screentime.app_name),
+    screentime.app_name
+)";
+
+/// SQL condition matching the given activities, the names are inlined since they are not user input.
+fn activity_filter(activities: &[Activity]) -> String {
+    let names: Vec<String> = activities
+        .iter()
+        .map(|activity| format!("'{}'", activity.name()))
+        .collect();
+    format!("activity IN ({})", names.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// Path of a database file for one test, removed once dropped.
+    struct TestDb(PathBuf);
+
+    impl TestDb {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("waysted-{}-{name}.db", process::id()));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TestDb {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// A database with a minute of each app recorded.
+    fn database(db: &TestDb, apps: &[&str]) -> Database {
+        let database = Database::new(&db.0, true).unwrap();
+        for (i, app_name) in apps.iter().enumerate() {
+            let start = Utc.timestamp_millis_opt(i as i64 * 60_000).unwrap();
+            database.log_focus_duration(
+                WindowInfo {
+                    title: String::new(),
+                    app_name: app_name.to_string(),
+                    id: None,
+                },
+                Duration::from_secs(60),
+                start,
+                start + Duration::from_secs(60),
+                Activity::Active,
+            );
+        }
+        database
+    }
+
+    /// Minutes per app, with the aliases applied.
+    fn screentime(database: &Database) -> Vec<(String, u128)> {
+        let end = Utc.timestamp_millis_opt(i64::from(u32::MAX)).unwrap();
+        let mut screentime: Vec<_> = database
+            .get_screentime_in_range(DateTime::UNIX_EPOCH, end, &Activity::ALL)
+            .unwrap()
+            .into_iter()
+            .map(|app| (app.app_name, app.duration / 60_000))
+            .collect();
+        screentime.sort();
+        screentime
+    }
+
+    fn apps(database: &Database) -> Vec<(String, Option<String>)> {
+        database
+            .get_apps()
+            .unwrap()
+            .into_iter()
+            .map(|app| (app.app_name, app.alias))
+            .collect()
+    }
+
+    #[test]
+    fn adds_the_activity_column_to_old_databases() {
+        let db = TestDb::new("migration");
+        let connection = Connection::open(&db.0).unwrap();
+        connection
+            .execute(
+                "CREATE TABLE screentime (
+                    id INTEGER PRIMARY KEY,
+                    title TEXT NOT NULL,
+                    app_name TEXT NOT NULL,
+                    duration INTEGER NOT NULL,
+                    start_timestamp NOT NULL,
+                    end_timestamp NOT NULL
+                )",
+                (),
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO screentime (title, app_name, duration, start_timestamp, end_timestamp)
+                 VALUES ('README.md', 'foot', 1000, 1000, 2000)",
+                (),
+            )
+            .unwrap();
+        drop(connection);
+
+        let database = Database::new(&db.0, false).unwrap();
+        let start = Utc.timestamp_millis_opt(0).unwrap();
+        let end = Utc.timestamp_millis_opt(10_000).unwrap();
+        database.log_focus_duration(
+            WindowInfo {
+                title: "README.md".to_owned(),
+                app_name: "foot".to_owned(),
+                id: None,
+            },
+            Duration::from_secs(1),
+            Utc.timestamp_millis_opt(3000).unwrap(),
+            Utc.timestamp_millis_opt(4000).unwrap(),
+            Activity::Idle,
+        );
+
+        let activities: Vec<Activity> = database
+            .get_logs(start, end, &Activity::ALL)
+            .unwrap()
+            .into_iter()
+            .map(|instance| instance.activity)
+            .collect();
+        assert_eq!(activities, [Activity::Idle, Activity::Active]);
+        assert_eq!(
+            database
+                .get_logs(start, end, &[Activity::Active])
+                .unwrap()
+                .len(),
+            1
+        );
+        drop(database);
+
+        // the migration only runs once
+        Database::new(&db.0, false).unwrap();
+    }
+
+    #[test]
+    fn aliases_match_case_insensitively() {
+        let db = TestDb::new("alias-case");
+        let database = database(&db, &["org.mozilla.firefox", "Firefox", "foot"]);
+        database
+            .set_alias("ORG.MOZILLA.FIREFOX", "firefox")
+            .unwrap();
+        database.set_alias("firefox", "firefox").unwrap();
+
+        assert_eq!(
+            screentime(&database),
+            [("firefox".to_owned(), 2), ("foot".to_owned(), 1)]
+        );
+        assert_eq!(
+            database
+                .get_alias("Org.Mozilla.Firefox")
+                .unwrap()
+                .as_deref(),
+            Some("firefox")
+        );
+        assert_eq!(database.get_alias("foot").unwrap(), None);
+    }
+
+    #[test]
+    fn stored_aliases_override_config_aliases() {
+        let db = TestDb::new("alias-config");
+        let database = database(&db, &["org.mozilla.firefox", "code"]);
+        let config_aliases = HashMap::from([
+            ("org.mozilla.firefox".to_owned(), "browser".to_owned()),
+            ("Code".to_owned(), "vscode".to_owned()),
+        ]);
+        database.set_config_aliases(&config_aliases).unwrap();
+        database
+            .set_alias("org.mozilla.firefox", "firefox")
+            .unwrap();
+
+        assert_eq!(
+            screentime(&database),
+            [("firefox".to_owned(), 1), ("vscode".to_owned(), 1)]
+        );
+
+        // the config aliases apply again once the stored one is removed
+        assert!(database.remove_alias("org.mozilla.firefox").unwrap());
+        assert_eq!(
+            screentime(&database),
+            [("browser".to_owned(), 1), ("vscode".to_owned(), 1)]
+        );
+
+        // reloading the config replaces its aliases
+        database.set_config_aliases(&HashMap::new()).unwrap();
+        assert_eq!(
+            screentime(&database),
+            [
+                ("code".to_owned(), 1),
+                ("org.mozilla.firefox".to_owned(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn unaliasing_restores_the_recorded_name() {
+        let db = TestDb::new("unalias");
+        let database = database(&db, &["org.mozilla.firefox"]);
+        database
+            .set_alias("org.mozilla.firefox", "firefox")
+            .unwrap();
+        assert_eq!(screentime(&database), [("firefox".to_owned(), 1)]);
+
+        assert!(database.remove_alias("ORG.mozilla.firefox").unwrap());
+        assert!(!database.remove_alias("org.mozilla.firefox").unwrap());
+        assert_eq!(
+            screentime(&database),
+            [("org.mozilla.firefox".to_owned(), 1)]
+        );
+    }
+
+    #[test]
+    fn lists_each_app_once() {
+        let db = TestDb::new("apps");
+        let database = database(&db, &["org.mozilla.firefox", "firefox", "firefox"]);
+        database
+            .set_alias("org.mozilla.firefox", "firefox")
+            .unwrap();
+        database.set_alias("FIREFOX", "firefox").unwrap();
+        database.set_alias("steam", "games").unwrap();
+        let config_aliases = HashMap::from([
+            ("Firefox".to_owned(), "browser".to_owned()),
+            ("code".to_owned(), "vscode".to_owned()),
+        ]);
+        database.set_config_aliases(&config_aliases).unwrap();
+
+        assert_eq!(
+            apps(&database),
+            [
+                ("code".to_owned(), Some("vscode".to_owned())),
+                ("firefox".to_owned(), Some("firefox".to_owned())),
+                ("org.mozilla.firefox".to_owned(), Some("firefox".to_owned())),
+                ("steam".to_owned(), Some("games".to_owned())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! Metadata of apps from their XDG desktop entries.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::database::AppScreentime;
+
+/// The parts of a `.desktop` file used to present an app.
+#[derive(Debug, Clone, Default)]
+pub struct DesktopEntry {
+    pub name: Option<String>,
+    pub icon: Option<String>,
+    pub categories: Vec<String>,
+    startup_wm_class: Option<String>,
+    /// Hidden entries count as deleted, they only hide the entries of later data directories
+    hidden: bool,
+    /// Not shown in menus, so its name isn't used as the display name
+    no_display: bool,
+}
+
+/// The desktop entries installed on the system.
+#[derive(Debug, Default)]
+pub struct DesktopEntries {
+    /// Entries by lowercase desktop file id
+    entries: HashMap<String, DesktopEntry>,
+    /// Desktop file ids by lowercase `StartupWMClass` and by the last part of reverse DNS ids
+    wm_classes: HashMap<String, String>,
+    short_ids: HashMap<String, String>,
+}
+
+impl DesktopEntries {
+    /// Read the entries from the `applications` directory of every XDG data directory.
+    pub fn load() -> Self {
+        Self::load_from(&data_dirs(|name| env::var(name).ok()))
+    }
+
+    /// Read the entries of the data directories, in order of precedence.
+    fn load_from(data_dirs: &[PathBuf]) -> Self {
+        let mut entries = HashMap::new();
+        for dir in data_dirs {
+            let dir = dir.join("applications");
+            read_dir(&dir, &dir, &mut entries);
+        }
+        entries.retain(|_, entry: &mut DesktopEntry| !entry.hidden);
+        debug!("Loaded {} desktop entries", entries.len());
+
+        // prefer the same entry on every run when several match
+        let mut ids: Vec<&String> = entries.keys().collect();
+        ids.sort();
+
+        let mut wm_classes = HashMap::new();
+        let mut short_ids = HashMap::new();
+        for id in ids {
+            if let Some(class) = &entries[id].startup_wm_class {
+                wm_classes
+                    .entry(class.to_lowercase())
+                    .or_insert_with(|| id.clone());
+            }
+            if let Some((_, short_id)) = id.rsplit_once('.') {
+                short_ids
+                    .entry(short_id.to_owned())
+                    .or_insert_with(|| id.clone());
+            }
+        }
+
+        Self {
+            entries,
+            wm_classes,
+            short_ids,
+        }
+    }
+
+    /// Find the entry of an app by its desktop file id, its `StartupWMClass`, or the last part of
+    /// a reverse DNS id, e.g. `wezterm` for `org.wezfurlong.wezterm`.
+    pub fn find(&self, app_name: &str) -> Option<&DesktopEntry> {
+        let app_name = app_name.to_lowercase();
+        let id = if self.entries.contains_key(&app_name) {
+            &app_name
+        } else {
+            self.wm_classes
+                .get(&app_name)
+                .or_else(|| self.short_ids.get(&app_name))?
+        };
+        self.entries.get(id)
+    }
+
+    /// Fill in the display name, icon and categories of the apps with a desktop entry.
+    pub fn annotate(&self, apps: &mut [AppScreentime]) {
+        for app in apps {
+            if let Some(entry) = self.find(&app.app_name) {
+                app.display_name = entry.name.clone().filter(|_| !entry.no_display);
+                app.icon = entry.icon.clone();
+                app.categories = entry.categories.clone();
+            }
+        }
+    }
+}
+
+/// `$XDG_DATA_HOME` followed by `$XDG_DATA_DIRS`, in order of precedence, with the environment
+/// variables looked up by `env_var`.
+fn data_dirs(env_var: impl Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let data_home = env_var("XDG_DATA_HOME")
+        .or_else(|| env_var("HOME").map(|home| format!("{home}/.local/share")));
+    let data_dirs = env_var("XDG_DATA_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(str::to_owned))
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Add the entries below `dir`, keeping the ones found in earlier data directories.
+fn read_dir(root: &Path, dir: &Path, entries: &mut HashMap<String, DesktopEntry>) {
+    let Ok(dir_entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for dir_entry in dir_entries.flatten() {
+        let path = dir_entry.path();
+        if path.is_dir() {
+            read_dir(root, &path, entries);
+            continue;
+        }
+
+        // the id of applications/foo/bar.desktop is foo-bar
+        let Some(id) = path
+            .strip_prefix(root)
+            .ok()
+            .and_then(|path| path.to_str())
+            .and_then(|path| path.strip_suffix(".desktop"))
+            .map(|id| id.replace('/', "-").to_lowercase())
+        else {
+            continue;
+        };
+
+        if !entries.contains_key(&id)
+            && let Ok(contents) = fs::read_to_string(&path)
+        {
+            entries.insert(id, parse(&contents));
+        }
+    }
+}
+
+/// Parse the keys of the `[Desktop Entry]` group, localized keys are ignored.
+fn parse(contents: &str) -> DesktopEntry {
+    let mut entry = DesktopEntry::default();
+    let mut in_group = false;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_group {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().to_owned();
+        match key.trim() {
+            "Name" => entry.name = Some(value),
+            "Icon" => entry.icon = Some(value),
+            "Categories" => {
+                entry.categories = value
+                    .split(';')
+                    .filter(|category| !category.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            }
+            "StartupWMClass" => entry.startup_wm_class = Some(value),
+            "Hidden" => entry.hidden = value == "true",
+            "NoDisplay" => entry.no_display = value == "true",
+            _ => {}
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    /// Data directories of one test, removed once dropped.
+    struct DataDirs(PathBuf);
+
+    impl DataDirs {
+        fn new(name: &str) -> Self {
+            let root = env::temp_dir().join(format!("waysted-{}-{name}", process::id()));
+            let _ = fs::remove_dir_all(&root);
+            Self(root)
+        }
+
+        /// Write `applications/<path>` in the data directory `dir`.
+        fn write(&self, dir: &str, path: &str, contents: &str) {
+            let path = self.0.join(dir).join("applications").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        fn load(&self, dirs: &[&str]) -> DesktopEntries {
+            let dirs: Vec<PathBuf> = dirs.iter().map(|dir| self.0.join(dir)).collect();
+            DesktopEntries::load_from(&dirs)
+        }
+    }
+
+    impl Drop for DataDirs {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn name(entries: &DesktopEntries, app_name: &str) -> Option<String> {
+        entries.find(app_name)?.name.clone()
+    }
+
+    #[test]
+    fn parses_the_desktop_entry_group() {
+        let entry = parse(
+            "# comment
+            [Desktop Entry]
+            Name=WezTerm
+            Name[de]=WezTerm DE
+            Icon = org.wezfurlong.wezterm
+            Categories=System;TerminalEmulator;
+            StartupWMClass=org.wezfurlong.wezterm
+
+            [Desktop Action new-window]
+            Name=New Window
+            Icon=window-new",
+        );
+
+        assert_eq!(entry.name.as_deref(), Some("WezTerm"));
+        assert_eq!(entry.icon.as_deref(), Some("org.wezfurlong.wezterm"));
+        assert_eq!(entry.categories, ["System", "TerminalEmulator"]);
+        assert_eq!(
+            entry.startup_wm_class.as_deref(),
+            Some("org.wezfurlong.wezterm")
+        );
+        assert!(!entry.hidden && !entry.no_display);
+    }
+
+    #[test]
+    fn finds_entries_by_id_wm_class_and_short_id() {
+        let dirs = DataDirs::new("desktop-find");
+        dirs.write(
+            "share",
+            "org.wezfurlong.wezterm.desktop",
+            "[Desktop Entry]\nName=WezTerm",
+        );
+        dirs.write(
+            "share",
+            "code.desktop",
+            "[Desktop Entry]\nName=Visual Studio Code\nStartupWMClass=Code",
+        );
+        dirs.write(
+            "share",
+            "kde/org.kde.kate.desktop",
+            "[Desktop Entry]\nName=Kate",
+        );
+        let entries = dirs.load(&["share"]);
+
+        assert_eq!(
+            name(&entries, "org.wezfurlong.wezterm").as_deref(),
+            Some("WezTerm")
+        );
+        assert_eq!(name(&entries, "wezterm").as_deref(), Some("WezTerm"));
+        assert_eq!(
+            name(&entries, "code").as_deref(),
+            Some("Visual Studio Code")
+        );
+        assert_eq!(
+            name(&entries, "CODE").as_deref(),
+            Some("Visual Studio Code")
+        );
+        // entries in subdirectories get the directory as prefix of their id
+        assert_eq!(name(&entries, "kde-org.kde.kate").as_deref(), Some("Kate"));
+        assert_eq!(name(&entries, "org.kde.kate"), None);
+        assert_eq!(name(&entries, "firefox"), None);
+    }
+
+    #[test]
+    fn prefers_earlier_data_directories() {
+        let dirs = DataDirs::new("desktop-precedence");
+        dirs.write("home", "foot.desktop", "[Desktop Entry]\nName=My Foot");
+        dirs.write("share", "foot.desktop", "[Desktop Entry]\nName=Foot");
+        dirs.write("share", "firefox.desktop", "[Desktop Entry]\nName=Firefox");
+        dirs.write(
+            "home",
+            "firefox.desktop",
+            "[Desktop Entry]\nName=Firefox\nHidden=true",
+        );
+        let entries = dirs.load(&["home", "share"]);
+
+        assert_eq!(name(&entries, "foot").as_deref(), Some("My Foot"));
+        // hidden entries delete the entries they override
+        assert_eq!(name(&entries, "firefox"), None);
+    }
+
+    #[test]
+    fn annotates_apps() {
+        let dirs = DataDirs::new("desktop-annotate");
+        dirs.write(
+            "share",
+            "foot.desktop",
+            "[Desktop Entry]\nName=Foot\nIcon=foot\nCategories=System;",
+        );
+        dirs.write(
+            "share",
+            "footclient.desktop",
+            "[Desktop Entry]\nName=Foot Client\nIcon=foot\nNoDisplay=true",
+        );
+        let entries = dirs.load(&["share"]);
+
+        let mut apps: Vec<AppScreentime> = ["foot", "footclient", "unknown"]
+            .into_iter()
+            .map(|app_name| AppScreentime {
+                id: 1,
+                app_name: app_name.to_owned(),
+                duration: 0,
+                percentage: 0,
+                display_name: None,
+                icon: None,
+                categories: Vec::new(),
+            })
+            .collect();
+        entries.annotate(&mut apps);
+
+        assert_eq!(apps[0].display_name.as_deref(), Some("Foot"));
+        assert_eq!(apps[0].categories, ["System"]);
+        assert_eq!(apps[1].display_name, None);
+        assert_eq!(apps[1].icon.as_deref(), Some("foot"));
+        assert_eq!(apps[2].display_name, None);
+    }
+
+    #[test]
+    fn data_home_comes_first() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        assert_eq!(
+            data_dirs(env(&[
+                ("HOME", "/home/user"),
+                ("XDG_DATA_HOME", "/data"),
+                ("XDG_DATA_DIRS", "/a:/b"),
+            ])),
+            [Path::new("/data"), Path::new("/a"), Path::new("/b")]
+        );
+        assert_eq!(
+            data_dirs(env(&[("HOME", "/home/user"), ("XDG_DATA_DIRS", "")])),
+            [
+                Path::new("/home/user/.local/share"),
+                Path::new("/usr/local/share"),
+                Path::new("/usr/share"),
+            ]
+        );
+    }
+}
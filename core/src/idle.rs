@@ -0,0 +1,177 @@
+//! Idle detection through the ext-idle-notify protocol.
+
+use std::{io, sync::mpsc::Sender, time::Duration};
+
+use log::debug;
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+    globals::{GlobalListContents, registry_queue_init},
+    protocol::{wl_registry::WlRegistry, wl_seat::WlSeat},
+};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleEvent {
+    /// No user input was received for the idle timeout and no idle inhibitor is active
+    Idle,
+    /// User input was received or an idle inhibitor became active after being idle
+    Resumed,
+    /// No user input was received for the idle timeout, regardless of idle inhibitors
+    InputIdle,
+    /// User input was received after being input idle
+    InputResumed,
+}
+
+/// Whether the notification respects idle inhibitors.
+#[derive(Debug, Clone, Copy)]
+enum NotificationKind {
+    Idle,
+    InputIdle,
+}
+
+#[derive(Default)]
+struct IdleState {
+    /// Set while watching for idle changes
+    sender: Option<Sender<IdleEvent>>,
+}
+
+/// Notifies when the user goes idle and when they resume, supported by most compositors
+/// except GNOME.
+pub struct IdleWatcher {
+    event_queue: EventQueue<IdleState>,
+    state: IdleState,
+
+    // keep the notifications alive so the compositor keeps sending idle events
+    _notification: ExtIdleNotificationV1,
+    _input_notification: Option<ExtIdleNotificationV1>,
+}
+
+impl IdleWatcher {
+    pub fn new(timeout: Duration) -> io::Result<Self> {
+        let connection = Connection::connect_to_env().map_err(io::Error::other)?;
+        let (globals, mut event_queue) =
+            registry_queue_init::<IdleState>(&connection).map_err(io::Error::other)?;
+        let qh = event_queue.handle();
+
+        let notifier = globals
+            .bind::<ExtIdleNotifierV1, _, _>(&qh, 1..=2, ())
+            .map_err(|err| io::Error::new(io::ErrorKind::Unsupported, err))?;
+        let seat = globals
+            .bind::<WlSeat, _, _>(&qh, 1..=1, ())
+            .map_err(|err| io::Error::new(io::ErrorKind::Unsupported, err))?;
+
+        let timeout = timeout.as_millis().try_into().unwrap_or(u32::MAX);
+        let notification =
+            notifier.get_idle_notification(timeout, &seat, &qh, NotificationKind::Idle);
+
+        // version 2 can tell idle apart from an inhibited idle, e.g. while a video is playing
+        let input_notification = (notifier.version() >= 2).then(|| {
+            notifier.get_input_idle_notification(timeout, &seat, &qh, NotificationKind::InputIdle)
+        });
+        if input_notification.is_none() {
+            debug!("Input idle notifications are not supported by the compositor");
+        }
+
+        let mut state = IdleState::default();
+        event_queue
+            .roundtrip(&mut state)
+            .map_err(io::Error::other)?;
+
+        Ok(Self {
+            event_queue,
+            state,
+            _notification: notification,
+            _input_notification: input_notification,
+        })
+    }
+
+    /// Watch for idle changes and send them over the channel.
+    /// This method blocks the current thread and only returns if the wayland connection fails,
+    /// or once an event can't be sent because the receiver was dropped.
+    pub fn watch(&mut self, sender: Sender<IdleEvent>) -> io::Result<()> {
+        self.state.sender = Some(sender);
+
+        while self.state.sender.is_some() {
+            self.event_queue
+                .blocking_dispatch(&mut self.state)
+                .map_err(io::Error::other)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, NotificationKind> for IdleState {
+    fn event(
+        state: &mut Self,
+        _notification: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        kind: &NotificationKind,
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let idle_event = match (kind, event) {
+            (NotificationKind::Idle, ext_idle_notification_v1::Event::Idled) => IdleEvent::Idle,
+            (NotificationKind::Idle, ext_idle_notification_v1::Event::Resumed) => {
+                IdleEvent::Resumed
+            }
+            (NotificationKind::InputIdle, ext_idle_notification_v1::Event::Idled) => {
+                IdleEvent::InputIdle
+            }
+            (NotificationKind::InputIdle, ext_idle_notification_v1::Event::Resumed) => {
+                IdleEvent::InputResumed
+            }
+            _ => return,
+        };
+
+        debug!("Received idle event {idle_event:?}");
+        if let Some(sender) = &state.sender
+            && sender.send(idle_event).is_err()
+        {
+            debug!("Stopped watching for idle, the receiver was dropped");
+            state.sender = None;
+        }
+    }
+}
+
+impl Dispatch<ExtIdleNotifierV1, ()> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _notifier: &ExtIdleNotifierV1,
+        _event: <ExtIdleNotifierV1 as Proxy>::Event,
+        _data: &(),
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // the notifier has no events
+    }
+}
+
+impl Dispatch<WlSeat, ()> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _seat: &WlSeat,
+        _event: <WlSeat as Proxy>::Event,
+        _data: &(),
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // the seat is only needed to create the idle notification
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _registry: &WlRegistry,
+        _event: <WlRegistry as Proxy>::Event,
+        _data: &GlobalListContents,
+        _connection: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // globals are only needed once at startup
+    }
+}
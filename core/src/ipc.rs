@@ -0,0 +1,174 @@
+//! Messages exchanged with the daemon over its control socket, one JSON message per line.
+
+use std::{
+    env,
+    fs::{self, DirBuilder},
+    io::{self, BufRead, BufReader, Write},
+    iter,
+    os::unix::{
+        fs::{DirBuilderExt, MetadataExt},
+        net::UnixStream,
+    },
+    path::PathBuf,
+};
+
+use rustix::process::getuid;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    compositor::WindowInfo,
+    database::{Activity, AppScreentime},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Request {
+    /// The focused window and how long it has been counted for
+    Status,
+
+    /// Screentime per app including the focused window, timestamps are in epoch millis
+    Screentime {
+        start: i64,
+        end: i64,
+        activities: Vec<Activity>,
+    },
+
+    /// Stop counting time until resumed, or for the given duration in ms
+    Pause {
+        duration: Option<u64>,
+    },
+    Resume,
+
+    /// Receive a stream of updates after the response, until the connection is closed
+    Subscribe,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Status(Status),
+    Screentime(Vec<AppScreentime>),
+    Ok,
+    Error(String),
+}
+
+/// Sent to subscribed clients, the current state is sent right after subscribing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Update {
+    /// The focused window, None while it is ignored
+    Focus(Option<WindowInfo>),
+    Activity(Activity),
+    Paused(bool),
+
+    /// Today's screentime per app including the focused window, sent on focus changes and
+    /// at every checkpoint
+    Totals(Vec<AppScreentime>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Status {
+    pub window: Option<WindowInfo>,
+    pub activity: Activity,
+
+    /// duration in ms the focused window has been counted for, 0 while not counting
+    pub elapsed: u128,
+    pub paused: bool,
+
+    /// duration in ms until a timed pause ends
+    pub resumes_in: Option<u128>,
+}
+
+/// Location of the daemon's control socket, in `$XDG_RUNTIME_DIR` or else in a directory only
+/// the user can access.
+pub fn socket_path() -> io::Result<PathBuf> {
+    let dir = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => private_temp_dir()?,
+    };
+    Ok(dir.join("waysted.sock"))
+}
+
+/// `/tmp/waysted-$UID`, rejected if another user could have created or modified it.
+fn private_temp_dir() -> io::Result<PathBuf> {
+    let uid = getuid().as_raw();
+    let dir = env::temp_dir().join(format!("waysted-{uid}"));
+    if let Err(err) = DirBuilder::new().mode(0o700).create(&dir)
+        && err.kind() != io::ErrorKind::AlreadyExists
+    {
+        return Err(err);
+    }
+
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a directory only accessible by the user",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(dir)
+}
+
+/// Write a message followed by a newline.
+pub fn write_message<T: Serialize>(writer: &mut impl Write, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line)
+}
+
+/// Read the next message, returns None once the other end closed the connection.
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+/// Connection to the daemon's control socket.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// Connect to the daemon, fails if the daemon is not running.
+    pub fn connect() -> io::Result<Self> {
+        let stream = UnixStream::connect(socket_path()?)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    pub fn request(&mut self, request: &Request) -> io::Result<Response> {
+        write_message(&mut self.writer, request)?;
+        read_message(&mut self.reader)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The daemon closed the connection",
+            )
+        })
+    }
+
+    /// Subscribe to updates, the iterator ends when the daemon stops.
+    pub fn subscribe(mut self) -> io::Result<impl Iterator<Item = io::Result<Update>>> {
+        match self.request(&Request::Subscribe)? {
+            Response::Ok => {}
+            Response::Error(err) => return Err(io::Error::other(err)),
+            response => {
+                return Err(io::Error::other(format!(
+                    "Unexpected response {response:?}"
+                )));
+            }
+        }
+
+        Ok(iter::from_fn(move || {
+            read_message(&mut self.reader).transpose()
+        }))
+    }
+}
@@ -1,2 +1,10 @@
 pub mod compositor;
+pub mod config;
 pub mod database;
+pub mod desktop;
+pub mod idle;
+pub mod ipc;
+pub mod limits;
+pub mod notification;
+pub mod rules;
+pub mod session;
@@ -0,0 +1,286 @@
+//! Daily screentime limits of apps and categories from the config.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::{
+    config::parse_duration,
+    database::{AppScreentime, CategoryScreentime},
+    rules::{self, CategoryRule},
+};
+
+/// What a limit applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LimitTarget {
+    /// An app by its name, or its alias
+    App(String),
+    /// A category from the config
+    Category(String),
+}
+
+/// What to do once a limit is exceeded, in addition to the notification.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitAction {
+    /// Close the focused window if it counts towards the limit
+    Close,
+    /// Minimize the focused window if it counts towards the limit
+    Minimize,
+    /// Lock the screen
+    Lock,
+    /// Run a command with `sh -c`
+    Command(String),
+}
+
+/// The daily screentime allowed for an app or a category.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "LimitConfig")]
+pub struct Limit {
+    pub target: LimitTarget,
+    pub daily: Duration,
+
+    /// Percentages of the limit to warn at before it is exceeded, in ascending order
+    pub warn_at: Vec<u8>,
+    pub action: Option<LimitAction>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitConfig {
+    app: Option<String>,
+    category: Option<String>,
+    daily: String,
+    #[serde(default = "default_warn_at")]
+    warn_at: Vec<u8>,
+    action: Option<LimitAction>,
+}
+
+fn default_warn_at() -> Vec<u8> {
+    vec![80]
+}
+
+impl TryFrom<LimitConfig> for Limit {
+    type Error = String;
+
+    fn try_from(config: LimitConfig) -> Result<Self, Self::Error> {
+        let target = match (config.app, config.category) {
+            (Some(app), None) => LimitTarget::App(app),
+            (None, Some(category)) => LimitTarget::Category(category),
+            _ => return Err("a limit needs either an app or a category".to_owned()),
+        };
+
+        let mut warn_at = config.warn_at;
+        warn_at.retain(|percentage| *percentage < 100);
+        warn_at.sort_unstable();
+
+        Ok(Self {
+            target,
+            daily: parse_duration(&config.daily)?,
+            warn_at,
+            action: config.action,
+        })
+    }
+}
+
+impl Limit {
+    /// Name of the app or category, as shown in notifications.
+    pub fn name(&self) -> &str {
+        match &self.target {
+            LimitTarget::App(name) | LimitTarget::Category(name) => name,
+        }
+    }
+
+    /// Screentime in ms counted towards the limit, app names are compared case insensitively.
+    pub fn usage(&self, apps: &[AppScreentime], categories: &[CategoryScreentime]) -> u128 {
+        match &self.target {
+            LimitTarget::App(name) => apps
+                .iter()
+                .filter(|app| app.app_name.eq_ignore_ascii_case(name))
+                .map(|app| app.duration)
+                .sum(),
+            LimitTarget::Category(name) => categories
+                .iter()
+                .filter(|category| category.category == *name)
+                .map(|category| category.duration)
+                .sum(),
+        }
+    }
+
+    /// Whether time in a window counts towards the limit, `app_name` being the aliased name.
+    pub fn applies_to(&self, app_name: &str, title: &str, categories: &[CategoryRule]) -> bool {
+        match &self.target {
+            LimitTarget::App(name) => app_name.eq_ignore_ascii_case(name),
+            LimitTarget::Category(name) => rules::categorize(categories, app_name, title) == name,
+        }
+    }
+
+    /// The highest threshold reached by `usage` in percent of the limit, 100 once exceeded.
+    pub fn threshold(&self, usage: u128) -> Option<u8> {
+        let daily = self.daily.as_millis().max(1);
+        if usage >= daily {
+            return Some(100);
+        }
+
+        let percentage = usage * 100 / daily;
+        self.warn_at
+            .iter()
+            .rev()
+            .find(|warn_at| u128::from(**warn_at) <= percentage)
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(config: &str) -> Result<Limit, toml::de::Error> {
+        toml::from_str(config)
+    }
+
+    fn app(app_name: &str, minutes: u128) -> AppScreentime {
+        AppScreentime {
+            id: 1,
+            app_name: app_name.to_owned(),
+            duration: minutes * 60 * 1000,
+            percentage: 0,
+            display_name: None,
+            icon: None,
+            categories: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_limits() {
+        let firefox = limit(
+            r#"
+            app = "firefox"
+            daily = "2h"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(firefox.target, LimitTarget::App("firefox".to_owned()));
+        assert_eq!(firefox.daily, Duration::from_secs(2 * 60 * 60));
+        assert_eq!(firefox.warn_at, [80]);
+        assert_eq!(firefox.action, None);
+
+        let social = limit(
+            r#"
+            category = "social"
+            daily = "30m"
+            warn_at = [90, 100, 50]
+            action = { command = "notify-send" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(social.target, LimitTarget::Category("social".to_owned()));
+        assert_eq!(social.warn_at, [50, 90]);
+        assert_eq!(
+            social.action,
+            Some(LimitAction::Command("notify-send".to_owned()))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_limits() {
+        for config in [
+            r#"daily = "1h""#,
+            r#"app = "a"
+            category = "b"
+            daily = "1h""#,
+            r#"app = "a"
+            daily = "1 hour""#,
+            r#"app = "a"
+            daily = "1h"
+            action = "suspend""#,
+        ] {
+            assert!(limit(config).is_err(), "{config:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn reaches_the_highest_passed_threshold() {
+        let limit = limit(
+            r#"
+            app = "firefox"
+            daily = "1h"
+            warn_at = [50, 90]
+            "#,
+        )
+        .unwrap();
+        let minutes = |minutes: u128| limit.threshold(minutes * 60 * 1000);
+
+        assert_eq!(minutes(0), None);
+        assert_eq!(minutes(29), None);
+        assert_eq!(minutes(30), Some(50));
+        assert_eq!(minutes(53), Some(50));
+        assert_eq!(minutes(54), Some(90));
+        assert_eq!(minutes(59), Some(90));
+        assert_eq!(minutes(60), Some(100));
+        assert_eq!(minutes(600), Some(100));
+    }
+
+    #[test]
+    fn counts_usage_of_the_target() {
+        let apps = [app("firefox", 10), app("Firefox", 5), app("foot", 20)];
+        let categories = [CategoryScreentime {
+            category: "browsing".to_owned(),
+            duration: 15 * 60 * 1000,
+            percentage: 42,
+        }];
+
+        let firefox = limit(
+            r#"
+            app = "firefox"
+            daily = "1h"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(firefox.usage(&apps, &categories), 15 * 60 * 1000);
+        let browsing = limit(
+            r#"
+            category = "browsing"
+            daily = "1h"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(browsing.usage(&apps, &categories), 15 * 60 * 1000);
+        let social = limit(
+            r#"
+            category = "social"
+            daily = "1h"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(social.usage(&apps, &categories), 0);
+    }
+
+    #[test]
+    fn applies_to_windows_of_the_target() {
+        let categories = [CategoryRule {
+            name: "browsing".to_owned(),
+            apps: vec!["firefox".to_owned()],
+            ..Default::default()
+        }];
+
+        let firefox = limit(
+            r#"
+            app = "firefox"
+            daily = "1h"
+            "#,
+        )
+        .unwrap();
+        assert!(firefox.applies_to("Firefox", "GitHub", &categories));
+        assert!(!firefox.applies_to("foot", "GitHub", &categories));
+        let browsing = limit(
+            r#"
+            category = "browsing"
+            daily = "1h"
+            "#,
+        )
+        .unwrap();
+        assert!(browsing.applies_to("firefox", "GitHub", &categories));
+        assert!(!browsing.applies_to("foot", "~", &categories));
+    }
+}
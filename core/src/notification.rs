@@ -0,0 +1,61 @@
+//! Desktop notifications through the org.freedesktop.Notifications service.
+
+use std::{collections::HashMap, io};
+
+use zbus::{blocking::Connection, proxy, zvariant::Value};
+
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low,
+    Normal,
+    /// Stays visible until dismissed on most notification daemons
+    Critical,
+}
+
+/// Sends notifications to the notification daemon of the session.
+pub struct Notifier {
+    proxy: NotificationsProxyBlocking<'static>,
+}
+
+impl Notifier {
+    pub fn new() -> io::Result<Self> {
+        let connection = Connection::session().map_err(io::Error::other)?;
+        let proxy = NotificationsProxyBlocking::new(&connection).map_err(io::Error::other)?;
+        Ok(Self { proxy })
+    }
+
+    pub fn notify(&self, summary: &str, body: &str, urgency: Urgency) -> io::Result<()> {
+        let urgency: u8 = match urgency {
+            Urgency::Low => 0,
+            Urgency::Normal => 1,
+            Urgency::Critical => 2,
+        };
+        let hints = HashMap::from([("urgency", Value::from(urgency))]);
+
+        // -1 leaves the expiration to the notification daemon
+        self.proxy
+            .notify("waysted", 0, "", summary, body, &[], hints, -1)
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,487 @@
+//! Rules from the config that match windows by their app name and title.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::compositor::WindowInfo;
+
+/// App name the time of excluded windows is recorded under.
+pub const EXCLUDED_APP: &str = "excluded";
+
+/// Category of the apps that don't match any category rule.
+pub const UNCATEGORIZED: &str = "uncategorized";
+
+/// A regex, parsed when the config is loaded.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    /// The first capture group of the first match, or the whole match without groups.
+    pub fn extract<'a>(&self, text: &'a str) -> Option<&'a str> {
+        let captures = self.0.captures(text)?;
+        captures
+            .get(1)
+            .or_else(|| captures.get(0))
+            .map(|m| m.as_str())
+    }
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Regex::new(&pattern).map(Pattern)
+    }
+}
+
+/// What happens to the time spent in an ignored window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IgnoreAction {
+    /// The window is not recorded at all
+    #[default]
+    Drop,
+    /// The time is recorded under the `excluded` app without a title
+    Exclude,
+}
+
+/// Windows matching every condition of the rule are ignored.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "IgnoreRuleConfig")]
+pub struct IgnoreRule {
+    /// Exact app name
+    pub app: Option<String>,
+    /// Regex searched in the app name
+    pub app_regex: Option<Pattern>,
+    /// Regex searched in the title
+    pub title: Option<Pattern>,
+    pub action: IgnoreAction,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct IgnoreRuleConfig {
+    app: Option<String>,
+    app_regex: Option<Pattern>,
+    title: Option<Pattern>,
+    action: IgnoreAction,
+}
+
+impl TryFrom<IgnoreRuleConfig> for IgnoreRule {
+    type Error = String;
+
+    fn try_from(config: IgnoreRuleConfig) -> Result<Self, Self::Error> {
+        // a rule without conditions would ignore every window
+        if config.app.is_none() && config.app_regex.is_none() && config.title.is_none() {
+            return Err("an ignore rule needs an app, app_regex or title".to_owned());
+        }
+
+        Ok(Self {
+            app: config.app,
+            app_regex: config.app_regex,
+            title: config.title,
+            action: config.action,
+        })
+    }
+}
+
+impl IgnoreRule {
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        app_matches(&self.app, &self.app_regex, &window.app_name)
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&window.title))
+    }
+}
+
+/// What is stored of the window titles of an app
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleMode {
+    /// The title as is
+    #[default]
+    Full,
+    /// The HMAC-SHA256 of the title with the key of the install, so time can still be grouped
+    /// by title without the titles being recoverable by hashing guesses
+    Hash,
+    /// The first capture group of the regex, or the whole match, nothing if it doesn't match
+    Extract(Pattern),
+    /// An empty title
+    None,
+}
+
+/// Windows of apps matching every condition of the rule have their title stored as configured.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyRule {
+    /// Exact app name
+    pub app: Option<String>,
+    /// Regex searched in the app name
+    pub app_regex: Option<Pattern>,
+    pub title: TitleMode,
+}
+
+impl PrivacyRule {
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        app_matches(&self.app, &self.app_regex, &window.app_name)
+    }
+
+    /// Whether any of the rules hashes titles and so needs a [`TitleKey`].
+    pub fn any_hashed(rules: &[PrivacyRule]) -> bool {
+        rules
+            .iter()
+            .any(|rule| matches!(rule.title, TitleMode::Hash))
+    }
+}
+
+const TITLE_KEY_LENGTH: usize = 32;
+
+/// Random key of the title hashes, generated once per install and stored next to the database.
+/// Hashed titles are only comparable between databases sharing the key.
+#[derive(Clone)]
+pub struct TitleKey([u8; TITLE_KEY_LENGTH]);
+
+impl TitleKey {
+    /// Location of the key of the database at `db_path`.
+    pub fn path(db_path: &Path) -> PathBuf {
+        db_path.with_file_name("title.key")
+    }
+
+    /// Read the key at `path`, generating it readable only by the owner if it doesn't exist yet.
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        let mut key = [0; TITLE_KEY_LENGTH];
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)
+        {
+            Ok(mut file) => {
+                File::open("/dev/urandom")?.read_exact(&mut key)?;
+                file.write_all(&key)?;
+                file.sync_all()?;
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                let contents = fs::read(path)?;
+                key = contents.try_into().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} is not a title hash key", path.display()),
+                    )
+                })?;
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(Self(key))
+    }
+
+    fn hash(&self, title: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(title.as_bytes());
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+}
+
+/// Windows matching every condition of the rule belong to the category.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CategoryRule {
+    pub name: String,
+    /// App names compared case insensitively, any app if empty
+    pub apps: Vec<String>,
+    /// Regex searched in the app name
+    pub app_regex: Option<Pattern>,
+    /// Regex searched in the title
+    pub title: Option<Pattern>,
+}
+
+impl CategoryRule {
+    pub fn matches(&self, app_name: &str, title: &str) -> bool {
+        (self.apps.is_empty()
+            || self
+                .apps
+                .iter()
+                .any(|app| app.eq_ignore_ascii_case(app_name)))
+            && app_matches(&None, &self.app_regex, app_name)
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(title))
+    }
+}
+
+fn app_matches(app: &Option<String>, app_regex: &Option<Pattern>, app_name: &str) -> bool {
+    app.as_ref().is_none_or(|app| app == app_name)
+        && app_regex
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(app_name))
+}
+
+/// The window to record according to the first matching rule, None if it shouldn't be recorded.
+pub fn filter_window(rules: &[IgnoreRule], window: WindowInfo) -> Option<WindowInfo> {
+    match rules.iter().find(|rule| rule.matches(&window)) {
+        None => Some(window),
+        Some(rule) => match rule.action {
+            IgnoreAction::Drop => None,
+            IgnoreAction::Exclude => Some(WindowInfo {
+                title: String::new(),
+                app_name: EXCLUDED_APP.to_owned(),
+                id: window.id,
+            }),
+        },
+    }
+}
+
+/// The window with its title stored according to the first matching rule. Titles to hash are
+/// dropped without a key.
+pub fn redact_title(
+    rules: &[PrivacyRule],
+    key: Option<&TitleKey>,
+    mut window: WindowInfo,
+) -> WindowInfo {
+    let Some(rule) = rules.iter().find(|rule| rule.matches(&window)) else {
+        return window;
+    };
+
+    window.title = match &rule.title {
+        // keep excluded windows without a title
+        _ if window.title.is_empty() => String::new(),
+        TitleMode::Full => window.title,
+        TitleMode::Hash => key.map(|key| key.hash(&window.title)).unwrap_or_default(),
+        TitleMode::Extract(pattern) => pattern.extract(&window.title).unwrap_or("").to_owned(),
+        TitleMode::None => String::new(),
+    };
+    window
+}
+
+/// The category of the first matching rule.
+pub fn categorize<'a>(rules: &'a [CategoryRule], app_name: &str, title: &str) -> &'a str {
+    rules
+        .iter()
+        .find(|rule| rule.matches(app_name, title))
+        .map_or(UNCATEGORIZED, |rule| &rule.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, os::unix::fs::PermissionsExt, process};
+
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+
+    /// Parse the `[[rule]]` tables of `config`.
+    fn rules<T: DeserializeOwned>(config: &str) -> Vec<T> {
+        #[derive(Deserialize)]
+        struct Rules<T> {
+            rule: Vec<T>,
+        }
+
+        toml::from_str::<Rules<T>>(config).unwrap().rule
+    }
+
+    fn window(title: &str, app_name: &str) -> WindowInfo {
+        WindowInfo {
+            title: title.to_owned(),
+            app_name: app_name.to_owned(),
+            id: Some(7),
+        }
+    }
+
+    #[test]
+    fn ignore_rules_match_every_condition() {
+        let rules: Vec<IgnoreRule> = rules(
+            r#"
+            [[rule]]
+            app = "org.keepassxc.KeePassXC"
+
+            [[rule]]
+            app_regex = "^(firefox|librewolf)$"
+            title = "Private Browsing"
+            "#,
+        );
+
+        assert!(rules[0].matches(&window("Passwords", "org.keepassxc.KeePassXC")));
+        assert!(!rules[0].matches(&window("Passwords", "keepassxc")));
+        assert!(rules[1].matches(&window("Mozilla Firefox Private Browsing", "firefox")));
+        assert!(!rules[1].matches(&window("Mozilla Firefox", "firefox")));
+        assert!(!rules[1].matches(&window("Private Browsing", "firefox-esr")));
+    }
+
+    #[test]
+    fn filters_by_the_first_matching_rule() {
+        let rules: Vec<IgnoreRule> = rules(
+            r#"
+            [[rule]]
+            title = "Private"
+            action = "exclude"
+
+            [[rule]]
+            app = "firefox"
+            "#,
+        );
+
+        assert_eq!(
+            filter_window(&rules, window("Private Browsing", "firefox")),
+            Some(WindowInfo {
+                title: String::new(),
+                app_name: EXCLUDED_APP.to_owned(),
+                id: Some(7),
+            })
+        );
+        assert_eq!(filter_window(&rules, window("GitHub", "firefox")), None);
+        assert_eq!(
+            filter_window(&rules, window("README.md", "foot")),
+            Some(window("README.md", "foot"))
+        );
+    }
+
+    #[test]
+    fn redacts_titles_by_the_first_matching_rule() {
+        let rules: Vec<PrivacyRule> = rules(
+            r#"
+            [[rule]]
+            app = "thunderbird"
+            title = "none"
+
+            [[rule]]
+            app = "code"
+            title = { extract = " - ([^-]+) - Visual Studio Code$" }
+
+            [[rule]]
+            app_regex = "^org\\.kde\\."
+            title = "full"
+
+            [[rule]]
+            title = "hash"
+            "#,
+        );
+        let key = TitleKey([1; TITLE_KEY_LENGTH]);
+        let redact =
+            |title, app_name| redact_title(&rules, Some(&key), window(title, app_name)).title;
+
+        assert_eq!(redact("Inbox - Mozilla Thunderbird", "thunderbird"), "");
+        assert_eq!(
+            redact("main.rs - waysted - Visual Studio Code", "code"),
+            "waysted"
+        );
+        assert_eq!(redact("Welcome", "code"), "");
+        assert_eq!(redact("main.rs - Kate", "org.kde.kate"), "main.rs - Kate");
+        assert_eq!(redact("~ : zsh", "foot"), key.hash("~ : zsh"));
+        assert_eq!(
+            redact_title(&rules, Some(&key), window("GitHub", "firefox")).id,
+            Some(7)
+        );
+
+        // excluded windows stay without a title
+        assert_eq!(redact("", "foot"), "");
+        // hashing without a key drops the title
+        assert_eq!(
+            redact_title(&rules, None, window("~ : zsh", "foot")).title,
+            ""
+        );
+        assert!(PrivacyRule::any_hashed(&rules));
+        assert!(!PrivacyRule::any_hashed(&rules[..3]));
+    }
+
+    #[test]
+    fn hashes_titles_with_the_key() {
+        let key = TitleKey([1; TITLE_KEY_LENGTH]);
+        let hash = key.hash("README.md");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, key.hash("README.md"));
+        assert_ne!(hash, key.hash("README.md "));
+        assert_ne!(hash, TitleKey([2; TITLE_KEY_LENGTH]).hash("README.md"));
+    }
+
+    #[test]
+    fn creates_the_title_key_once() {
+        let path = env::temp_dir().join(format!("waysted-{}-title.key", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let key = TitleKey::load_or_create(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let loaded = TitleKey::load_or_create(&path).unwrap();
+        fs::write(&path, "short").unwrap();
+        let invalid = TitleKey::load_or_create(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(key.0, loaded.0);
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn categorizes_by_the_first_matching_rule() {
+        let rules: Vec<CategoryRule> = rules(
+            r#"
+            [[rule]]
+            name = "entertainment"
+            app_regex = "^(firefox|chromium)$"
+            title = "YouTube|Twitch"
+
+            [[rule]]
+            name = "development"
+            apps = ["code", "org.wezfurlong.wezterm"]
+
+            [[rule]]
+            name = "browsing"
+            apps = ["firefox"]
+            "#,
+        );
+
+        assert_eq!(
+            categorize(&rules, "firefox", "Rust - YouTube"),
+            "entertainment"
+        );
+        assert_eq!(categorize(&rules, "firefox", "GitHub"), "browsing");
+        assert_eq!(categorize(&rules, "chromium", "GitHub"), UNCATEGORIZED);
+        assert_eq!(categorize(&rules, "code", "YouTube"), "development");
+        assert_eq!(categorize(&rules, "wezterm", ""), UNCATEGORIZED);
+        assert_eq!(categorize(&[], "code", ""), UNCATEGORIZED);
+    }
+
+    #[test]
+    fn categorizes_apps_case_insensitively() {
+        let rules: Vec<CategoryRule> = rules(
+            r#"
+            [[rule]]
+            name = "chat"
+            apps = ["Slack", "vesktop"]
+            "#,
+        );
+
+        assert_eq!(categorize(&rules, "slack", ""), "chat");
+        assert_eq!(categorize(&rules, "Vesktop", ""), "chat");
+        assert_eq!(categorize(&rules, "slack-desktop", ""), UNCATEGORIZED);
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(toml::from_str::<IgnoreRule>(r#"title = "(""#).is_err());
+        assert!(toml::from_str::<IgnoreRule>(r#"apps = ["foot"]"#).is_err());
+    }
+
+    #[test]
+    fn rejects_ignore_rules_without_conditions() {
+        assert!(toml::from_str::<IgnoreRule>("").is_err());
+        assert!(toml::from_str::<IgnoreRule>(r#"action = "exclude""#).is_err());
+        assert!(toml::from_str::<IgnoreRule>(r#"title = "Private Browsing""#).is_ok());
+    }
+}
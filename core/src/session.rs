@@ -0,0 +1,135 @@
+//! Lock screen, suspend and session state reported by logind.
+
+use std::{io, sync::mpsc::Sender, thread};
+
+use log::{debug, error};
+use zbus::{blocking::Connection, proxy, zvariant::OwnedObjectPath};
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn get_session(&self, session_id: &str) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+trait Session {
+    fn lock(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn active(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+}
+
+/// Ask logind to lock the session the daemon runs in, the screen locker reacts to the Lock signal.
+pub fn lock_session() -> io::Result<()> {
+    let connection = Connection::system().map_err(io::Error::other)?;
+    SessionProxyBlocking::new(&connection)
+        .and_then(|session| session.lock())
+        .map_err(io::Error::other)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// The screen locker set or cleared the session's locked hint
+    Locked(bool),
+    /// The system is about to suspend or has woken up
+    Sleeping(bool),
+    /// The session was switched to or away from, e.g. with a VT switch
+    Active(bool),
+}
+
+/// Watches the logind session the daemon runs in.
+pub struct SessionWatcher {
+    manager: ManagerProxyBlocking<'static>,
+    session: SessionProxyBlocking<'static>,
+}
+
+impl SessionWatcher {
+    pub fn new() -> io::Result<Self> {
+        let connection = Connection::system().map_err(io::Error::other)?;
+        let manager = ManagerProxyBlocking::new(&connection).map_err(io::Error::other)?;
+
+        // signals are emitted from the session's own path, not from the `auto` alias
+        let session_id = SessionProxyBlocking::new(&connection)
+            .and_then(|session| session.id())
+            .map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No logind session found, {err}"),
+                )
+            })?;
+        let session_path = manager.get_session(&session_id).map_err(io::Error::other)?;
+        let session = SessionProxyBlocking::builder(&connection)
+            .path(session_path)
+            .and_then(|builder| builder.build())
+            .map_err(io::Error::other)?;
+        debug!("Watching logind session {session_id}");
+
+        Ok(Self { manager, session })
+    }
+
+    /// Watch for session changes and send them over the channel, starting with the current state.
+    /// This method blocks the current thread and only returns if logind leaves the system bus.
+    pub fn watch(&self, sender: Sender<SessionEvent>) -> io::Result<()> {
+        let send = |event| {
+            debug!("Received session event {event:?}");
+            if let Err(err) = sender.send(event) {
+                error!("Failed to send session event: {err}");
+            }
+        };
+
+        let sleep = self
+            .manager
+            .receive_prepare_for_sleep()
+            .map_err(io::Error::other)?;
+        // the Lock and Unlock signals only ask the screen locker to act, lockers that don't set
+        // the locked hint such as swaylock never answer with Unlock, so entering the locked
+        // state on Lock would never leave it. The lock state comes from the locked hint alone.
+        // Property change streams yield the current value first
+        let locked_hint = self.session.receive_locked_hint_changed();
+        let active = self.session.receive_active_changed();
+
+        // every signal is received on its own blocking iterator
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for signal in sleep {
+                    match signal.args() {
+                        Ok(args) => send(SessionEvent::Sleeping(args.start)),
+                        Err(err) => error!("Invalid PrepareForSleep signal: {err}"),
+                    }
+                }
+            });
+            scope.spawn(|| {
+                for change in locked_hint {
+                    if let Ok(locked) = change.get() {
+                        send(SessionEvent::Locked(locked));
+                    }
+                }
+            });
+            scope.spawn(|| {
+                for change in active {
+                    if let Ok(active) = change.get() {
+                        send(SessionEvent::Active(active));
+                    }
+                }
+            });
+        });
+
+        Ok(())
+    }
+}
@@ -1,61 +1,571 @@
-use std::{io, sync::mpsc::channel, thread::spawn, time::Instant};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{RecvTimeoutError, Sender, channel},
+    },
+    thread::spawn,
+    time::{Duration, Instant},
+};
 
-use chrono::Utc;
-use log::{debug, info};
+use chrono::DateTime;
+use inotify::{EventMask, Inotify, WatchMask};
+use log::{error, info, warn};
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+};
 use waysted_core::{
-    compositor::{Compositor, WindowInfo, get_current_compositor},
-    database::Database,
+    compositor::{Compositor, CompositorKind, WindowInfo, get_compositor},
+    config::Config,
+    database::{Activity, Database},
+    idle::{IdleEvent, IdleWatcher},
+    ipc::{Request, Response, Update},
+    limits::Limit,
+    rules::{CategoryRule, IgnoreRule, PrivacyRule},
+    session::{SessionEvent, SessionWatcher},
+};
+
+use crate::{
+    limits::LimitChecker,
+    server::Server,
+    supervisor::Supervisor,
+    tracker::{Inactivity, Tracker},
 };
 
+/// Everything the tracker reacts to, merged into a single channel.
+pub enum Event {
+    Focus(WindowInfo),
+    Idle(IdleEvent),
+    Session(SessionEvent),
+    /// A client request, answered over the included channel
+    Request(Request, Sender<Response>),
+    /// A client subscribed to updates
+    Subscribe(Sender<Update>),
+    /// The config file changed or a reload was requested with SIGHUP
+    Reload,
+    /// A termination signal was received
+    Shutdown,
+}
+
 pub struct Daemon {
+    compositor_kind: CompositorKind,
     compositor: Box<dyn Compositor>,
+    db_path: PathBuf,
+
+    /// None when idle detection is disabled
+    idle_timeout: Option<Duration>,
+
+    /// How often the span of the focused window is persisted
+    checkpoint_interval: Duration,
+
+    ignore_rules: Vec<IgnoreRule>,
+    privacy_rules: Vec<PrivacyRule>,
+    aliases: HashMap<String, String>,
+    categories: Vec<CategoryRule>,
+    limits: Vec<Limit>,
 }
 
 impl Daemon {
-    pub fn new() -> io::Result<Self> {
+    pub fn new(config: &Config) -> io::Result<Self> {
+        let compositor_kind = match config.daemon.compositor {
+            Some(kind) => {
+                info!("Using the {kind} compositor.");
+                kind
+            }
+            None => {
+                let kind = CompositorKind::detect()?;
+                info!("{kind} compositor found.");
+                kind
+            }
+        };
+
+        let idle_timeout = config.daemon.idle_timeout;
         Ok(Self {
-            compositor: get_current_compositor()?,
+            compositor_kind,
+            compositor: get_compositor(compositor_kind)?,
+            db_path: config.database_path(),
+            idle_timeout: (idle_timeout > 0).then(|| Duration::from_secs(idle_timeout)),
+            checkpoint_interval: Duration::from_secs(config.daemon.checkpoint_interval.max(1)),
+            ignore_rules: config.ignore.clone(),
+            privacy_rules: config.privacy.clone(),
+            aliases: config.aliases.clone(),
+            categories: config.categories.clone(),
+            limits: config.limits.clone(),
         })
     }
 
-    pub fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Run the event loop until a termination signal is received. `load_config` is called to
+    /// apply changes when the config at `config_path` is modified.
+    pub fn start(
+        self,
+        config_path: &Path,
+        load_config: impl Fn() -> io::Result<Config>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let (sender, receiver) = channel();
 
-        let db = Database::new(true)?;
-        let handle = spawn(move || {
-            let mut start_timestamp = Utc::now();
-            let mut start_time = Instant::now();
-            let mut focused_window: Option<WindowInfo> = None;
-            while let Ok(currently_focused_window) = receiver.recv() {
-                if let Some(previously_focused_window) = focused_window {
-                    let duration = start_time.elapsed();
-                    let end_timestamp = Utc::now();
-                    debug!(
-                        "{} - {} focused for {}ms",
-                        previously_focused_window.app_name,
-                        previously_focused_window.title,
-                        duration.as_millis()
-                    );
-
-                    db.log_focus_duration(
-                        previously_focused_window,
-                        duration,
-                        start_timestamp,
-                        end_timestamp,
-                    );
-                }
-
-                start_time = Instant::now();
-                start_timestamp = Utc::now();
-                focused_window = Some(currently_focused_window);
-            }
-        });
+        let db = Database::new(&self.db_path, true)?;
+        db.set_config_aliases(&self.aliases)?;
+        watch_signals(sender.clone())?;
+        watch_config(config_path, sender.clone());
 
         info!("Watching for changes in the focused window");
-        self.compositor.watch_focused_window(sender)?;
+        let compositor_kind = self.compositor_kind;
+        let compositor = self.compositor;
+        let focus_sender = forward(sender.clone(), Event::Focus);
+        let supervisor = Supervisor::spawn(compositor_kind, compositor, focus_sender);
+
+        let mut idle_timeout = self.idle_timeout;
+        let mut idle_watch =
+            idle_timeout.and_then(|idle_timeout| watch_idle(idle_timeout, sender.clone()));
+        watch_session(forward(sender.clone(), Event::Session));
+        let _server = Server::start(sender.clone())?;
+
+        let mut tracker = Tracker::new(db, self.ignore_rules, self.privacy_rules);
+        let mut limits = LimitChecker::new(
+            self.limits,
+            self.categories,
+            compositor_kind,
+            tracker.database(),
+        );
+        let mut idle = IdleState::default();
+        let mut checkpoint_interval = self.checkpoint_interval;
+        let mut next_checkpoint = Instant::now() + checkpoint_interval;
+        loop {
+            let mut timeout = next_checkpoint.saturating_duration_since(Instant::now());
+            if let Some(remaining) = tracker.pause_remaining() {
+                timeout = timeout.min(remaining);
+            }
+            let event = receiver.recv_timeout(timeout);
+
+            if tracker
+                .pause_remaining()
+                .is_some_and(|remaining| remaining.is_zero())
+            {
+                info!("Resuming tracking after the pause");
+                tracker.resume();
+            }
+
+            if Instant::now() >= next_checkpoint {
+                tracker.checkpoint();
+                tracker.publish_totals();
+                limits.check(tracker.database(), tracker.focused_window());
+                next_checkpoint = Instant::now() + checkpoint_interval;
+            }
+
+            let event = match event {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            let now = Instant::now();
+            match event {
+                Event::Focus(window_info) => {
+                    tracker.focus_changed(window_info);
+                    limits.check(tracker.database(), tracker.focused_window());
+                }
+                Event::Idle(event) => {
+                    let since = idle.update(event, now, idle_timeout.unwrap_or_default());
+                    tracker.activity_changed(idle.activity(), since);
+                }
+                Event::Session(event) => {
+                    let (inactivity, inactive) = match event {
+                        SessionEvent::Locked(locked) => (Inactivity::Locked, locked),
+                        SessionEvent::Sleeping(sleeping) => (Inactivity::Sleeping, sleeping),
+                        SessionEvent::Active(active) => (Inactivity::SessionInactive, !active),
+                    };
+                    if inactive {
+                        tracker.inactive(inactivity, now);
+                    } else {
+                        tracker.active(inactivity);
+                    }
+                }
+                Event::Request(request, responder) => {
+                    let _ = responder.send(handle_request(&mut tracker, request));
+                }
+                Event::Subscribe(subscriber) => tracker.subscribe(subscriber),
+                Event::Reload => {
+                    let config = match load_config() {
+                        Ok(config) => config,
+                        Err(err) => {
+                            warn!("Failed to reload the config, keeping the current one: {err}");
+                            continue;
+                        }
+                    };
+                    info!("Reloading the config");
+
+                    if config
+                        .daemon
+                        .compositor
+                        .is_some_and(|kind| kind != compositor_kind)
+                    {
+                        warn!("Changing the compositor requires restarting the daemon");
+                    }
+                    if config.database_path() != self.db_path {
+                        warn!("Changing the database requires restarting the daemon");
+                    }
+
+                    if let Err(err) = tracker.database().set_config_aliases(&config.aliases) {
+                        error!("Failed to apply the aliases from the config: {err}");
+                    }
+                    tracker.set_rules(config.ignore, config.privacy);
+                    tracker.reload_desktop_entries();
+                    tracker.publish_totals();
+                    limits.set_limits(config.limits, tracker.database());
+                    limits.set_categories(config.categories);
+                    limits.check(tracker.database(), tracker.focused_window());
 
-        handle.join().unwrap();
+                    let interval = Duration::from_secs(config.daemon.checkpoint_interval.max(1));
+                    if interval != checkpoint_interval {
+                        checkpoint_interval = interval;
+                        next_checkpoint = now + checkpoint_interval;
+                    }
+
+                    let timeout = config.daemon.idle_timeout;
+                    let timeout = (timeout > 0).then(|| Duration::from_secs(timeout));
+                    if timeout != idle_timeout {
+                        // events of the previous watcher no longer apply to the new timeout
+                        idle_timeout = timeout;
+                        drop(idle_watch.take());
+                        idle = IdleState::default();
+                        tracker.activity_changed(idle.activity(), now);
+                        idle_watch = idle_timeout
+                            .and_then(|idle_timeout| watch_idle(idle_timeout, sender.clone()));
+                    }
+                }
+                Event::Shutdown => {
+                    tracker.finish();
+                    break;
+                }
+            }
+        }
 
+        // drop the compositor so it can clean up, e.g. unload the KWin script
+        supervisor.stop();
         Ok(())
     }
 }
+
+fn handle_request(tracker: &mut Tracker, request: Request) -> Response {
+    match request {
+        Request::Status => Response::Status(tracker.status()),
+        Request::Screentime {
+            start,
+            end,
+            activities,
+        } => {
+            let (Some(start), Some(end)) = (
+                DateTime::from_timestamp_millis(start),
+                DateTime::from_timestamp_millis(end),
+            ) else {
+                return Response::Error("Invalid timestamps".to_owned());
+            };
+
+            match tracker.screentime(start, end, &activities) {
+                Ok(screentime) => Response::Screentime(screentime),
+                Err(err) => Response::Error(err.to_string()),
+            }
+        }
+        Request::Pause { duration } => {
+            tracker.pause(duration.map(Duration::from_millis));
+            Response::Ok
+        }
+        Request::Resume => {
+            tracker.resume();
+            Response::Ok
+        }
+        // subscriptions are handled by the server since they take over the connection
+        Request::Subscribe => Response::Error("Unexpected subscription".to_owned()),
+    }
+}
+
+/// Reload the config on SIGHUP, and shutdown the event loop on the first termination signal so
+/// the focused span is persisted.
+fn watch_signals(sender: Sender<Event>) -> io::Result<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP])?;
+    spawn(move || {
+        for signal in signals.forever() {
+            if signal == SIGHUP {
+                if sender.send(Event::Reload).is_err() {
+                    break;
+                }
+                continue;
+            }
+
+            info!("Received signal {signal}, shutting down");
+            let _ = sender.send(Event::Shutdown);
+            break;
+        }
+    });
+    Ok(())
+}
+
+/// Send a reload event whenever the config file is written, replaced or linked. Until its
+/// directory exists, the nearest existing ancestor is watched for the directory to be created.
+fn watch_config(path: &Path, sender: Sender<Event>) {
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return;
+    };
+
+    let mut inotify = match Inotify::init() {
+        Ok(inotify) => inotify,
+        Err(err) => {
+            warn!("Not watching {} for changes, {err}", path.display());
+            return;
+        }
+    };
+
+    info!("Watching {} for changes", path.display());
+    let (path, dir, file_name) = (path.to_owned(), dir.to_owned(), file_name.to_owned());
+    spawn(move || {
+        let mut buffer = [0; 4096];
+        let mut dir_was_missing = false;
+        loop {
+            let Some(watched) = dir.ancestors().find(|dir| dir.is_dir()) else {
+                warn!("Stopped watching the config for changes, no parent directory exists");
+                return;
+            };
+            let watching_dir = watched == dir;
+
+            // editors often replace the file instead of writing to it, and dotfile managers
+            // create a symlink, so watch the directory
+            let mask = if watching_dir {
+                WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE
+            } else {
+                WatchMask::MOVED_TO | WatchMask::CREATE
+            };
+            let watch = match inotify.watches().add(
+                watched,
+                mask | WatchMask::DELETE_SELF | WatchMask::MOVE_SELF,
+            ) {
+                Ok(watch) => watch,
+                Err(err) => {
+                    warn!("Stopped watching the config for changes, {err}");
+                    return;
+                }
+            };
+
+            if !watching_dir {
+                warn!(
+                    "{} doesn't exist, watching {} until it is created",
+                    dir.display(),
+                    watched.display()
+                );
+                dir_was_missing = true;
+            } else if dir_was_missing && path.exists() {
+                // the config may have been written before its directory was watched
+                dir_was_missing = false;
+                if sender.send(Event::Reload).is_err() {
+                    return;
+                }
+            }
+
+            loop {
+                let events = match inotify.read_events_blocking(&mut buffer) {
+                    Ok(events) => events,
+                    Err(err) => {
+                        warn!("Stopped watching the config for changes, {err}");
+                        return;
+                    }
+                };
+
+                let mut reload = false;
+                let mut rewatch = false;
+                // skip the removal of the previous watch
+                for event in events.filter(|event| event.wd == watch) {
+                    if event.mask.intersects(
+                        EventMask::DELETE_SELF | EventMask::MOVE_SELF | EventMask::IGNORED,
+                    ) {
+                        rewatch = true;
+                    } else if !watching_dir {
+                        // a directory closer to the config's may have been created
+                        rewatch |= event.mask.contains(EventMask::ISDIR);
+                    } else if event.name == Some(file_name.as_os_str()) {
+                        // newly created files are reloaded once written
+                        reload |= !event.mask.contains(EventMask::CREATE) || !is_empty_file(&path);
+                    }
+                }
+
+                if reload && sender.send(Event::Reload).is_err() {
+                    return;
+                }
+                if rewatch {
+                    break;
+                }
+            }
+
+            // fails if the directory was removed along with the watch
+            let _ = inotify.watches().remove(watch);
+        }
+    });
+}
+
+fn is_empty_file(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.len() == 0)
+}
+
+/// Idle state of the notifications with and without idle inhibitors.
+#[derive(Default)]
+struct IdleState {
+    idle: bool,
+    input_idle: bool,
+}
+
+impl IdleState {
+    /// Apply the event at `now`, returning since when the new activity applies.
+    fn update(&mut self, event: IdleEvent, now: Instant, idle_timeout: Duration) -> Instant {
+        let previous = self.activity();
+        match event {
+            IdleEvent::Idle => self.idle = true,
+            IdleEvent::Resumed => self.idle = false,
+            IdleEvent::InputIdle => self.input_idle = true,
+            IdleEvent::InputResumed => self.input_idle = false,
+        }
+
+        // idle notifications are only sent after the timeout without input
+        match (previous, self.activity()) {
+            (Activity::Active, Activity::Passive | Activity::Idle)
+            | (Activity::Passive, Activity::Idle) => now.checked_sub(idle_timeout).unwrap_or(now),
+            _ => now,
+        }
+    }
+
+    /// Without input while idle is inhibited, the user is most likely watching something.
+    fn activity(&self) -> Activity {
+        if self.idle {
+            Activity::Idle
+        } else if self.input_idle {
+            Activity::Passive
+        } else {
+            Activity::Active
+        }
+    }
+}
+
+/// Create a channel whose messages are forwarded to the event channel.
+fn forward<T: Send + 'static>(events: Sender<Event>, into_event: fn(T) -> Event) -> Sender<T> {
+    let (sender, receiver) = channel::<T>();
+    spawn(move || {
+        for message in receiver {
+            if events.send(into_event(message)).is_err() {
+                break;
+            }
+        }
+    });
+    sender
+}
+
+/// Stops forwarding idle events when dropped, the watcher then stops on its next event.
+struct IdleWatch {
+    stopped: Arc<AtomicBool>,
+}
+
+impl Drop for IdleWatch {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+fn watch_idle(idle_timeout: Duration, events: Sender<Event>) -> Option<IdleWatch> {
+    let mut idle_watcher = match IdleWatcher::new(idle_timeout) {
+        Ok(idle_watcher) => idle_watcher,
+        Err(err) => {
+            warn!("Idle detection is not available, {err}");
+            return None;
+        }
+    };
+
+    info!("Watching for idle after {}s", idle_timeout.as_secs());
+    let stopped = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = channel();
+    spawn({
+        let stopped = stopped.clone();
+        move || {
+            for event in receiver {
+                if stopped.load(Ordering::Relaxed) || events.send(Event::Idle(event)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    spawn(move || {
+        if let Err(err) = idle_watcher.watch(sender) {
+            warn!("Stopped watching for idle, {err}");
+        }
+    });
+
+    Some(IdleWatch { stopped })
+}
+
+fn watch_session(sender: Sender<SessionEvent>) {
+    let session_watcher = match SessionWatcher::new() {
+        Ok(session_watcher) => session_watcher,
+        Err(err) => {
+            warn!("Lock screen and suspend detection is not available, {err}");
+            return;
+        }
+    };
+
+    spawn(move || {
+        if let Err(err) = session_watcher.watch(sender) {
+            warn!("Stopped watching the logind session, {err}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+    #[test]
+    fn idles_by_the_notifications() {
+        let mut idle = IdleState::default();
+        let now = Instant::now();
+        assert_eq!(idle.activity(), Activity::Active);
+
+        idle.update(IdleEvent::InputIdle, now, IDLE_TIMEOUT);
+        assert_eq!(idle.activity(), Activity::Passive);
+        idle.update(IdleEvent::Idle, now, IDLE_TIMEOUT);
+        assert_eq!(idle.activity(), Activity::Idle);
+        idle.update(IdleEvent::Resumed, now, IDLE_TIMEOUT);
+        assert_eq!(idle.activity(), Activity::Passive);
+        idle.update(IdleEvent::InputResumed, now, IDLE_TIMEOUT);
+        assert_eq!(idle.activity(), Activity::Active);
+
+        // the notification respecting idle inhibitors may arrive first
+        idle.update(IdleEvent::Idle, now, IDLE_TIMEOUT);
+        assert_eq!(idle.activity(), Activity::Idle);
+        idle.update(IdleEvent::InputIdle, now, IDLE_TIMEOUT);
+        assert_eq!(idle.activity(), Activity::Idle);
+    }
+
+    #[test]
+    fn backdates_going_idle_by_the_timeout() {
+        let mut idle = IdleState::default();
+        let now = Instant::now() + IDLE_TIMEOUT;
+        let idle_since = now - IDLE_TIMEOUT;
+
+        assert_eq!(
+            idle.update(IdleEvent::InputIdle, now, IDLE_TIMEOUT),
+            idle_since
+        );
+        assert_eq!(idle.update(IdleEvent::Idle, now, IDLE_TIMEOUT), idle_since);
+        assert_eq!(idle.update(IdleEvent::Resumed, now, IDLE_TIMEOUT), now);
+        assert_eq!(idle.update(IdleEvent::InputResumed, now, IDLE_TIMEOUT), now);
+
+        // an idle inhibitor ending while input idle is only noticed now
+        idle.update(IdleEvent::InputIdle, now, IDLE_TIMEOUT);
+        let later = now + IDLE_TIMEOUT;
+        assert_eq!(
+            idle.update(IdleEvent::Idle, later, IDLE_TIMEOUT),
+            later - IDLE_TIMEOUT
+        );
+        assert_eq!(idle.update(IdleEvent::Idle, later, IDLE_TIMEOUT), later);
+    }
+}
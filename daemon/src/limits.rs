@@ -0,0 +1,487 @@
+use std::{
+    collections::HashMap,
+    io, mem,
+    process::Command,
+    sync::mpsc::{Sender, channel},
+    thread::spawn,
+};
+
+use chrono::{Local, NaiveDate, NaiveTime};
+use log::{debug, error, info, warn};
+use waysted_core::{
+    compositor::{Compositor, CompositorKind, WindowAction, WindowInfo, get_compositor},
+    config::format_millis_short,
+    database::{Activity, AppScreentime, CategoryScreentime, Database},
+    limits::{Limit, LimitAction, LimitTarget},
+    notification::{Notifier, Urgency},
+    rules::CategoryRule,
+    session,
+};
+
+/// Notifies when today's screentime of an app or category approaches or exceeds its limit,
+/// and performs the action of exceeded limits while their apps are in use.
+pub struct LimitChecker {
+    limits: Vec<Limit>,
+    categories: Vec<CategoryRule>,
+    notifier: Option<Notifier>,
+    compositor_kind: CompositorKind,
+    /// Started on the first action
+    enforcer: Option<Sender<Enforcement>>,
+    /// The focused window on the last check, with its alias applied
+    window: Option<WindowInfo>,
+
+    /// The day the notifications were sent on
+    day: NaiveDate,
+    /// Highest threshold in percent each limit was notified for, 100 once exceeded
+    notified: HashMap<LimitTarget, u8>,
+}
+
+impl LimitChecker {
+    pub fn new(
+        limits: Vec<Limit>,
+        categories: Vec<CategoryRule>,
+        compositor_kind: CompositorKind,
+        db: &Database,
+    ) -> Self {
+        let mut checker = Self {
+            limits: Vec::new(),
+            categories,
+            notifier: None,
+            compositor_kind,
+            enforcer: None,
+            window: None,
+            day: Local::now().date_naive(),
+            notified: HashMap::new(),
+        };
+        checker.set_limits(limits, db);
+        checker
+    }
+
+    /// Apply a reloaded config, limits that were already notified today are not notified again.
+    /// Limits on an app that has an alias apply to the alias, like its screentime.
+    pub fn set_limits(&mut self, mut limits: Vec<Limit>, db: &Database) {
+        if !limits.is_empty() && self.notifier.is_none() {
+            self.notifier = Notifier::new()
+                .inspect_err(|err| warn!("Notifications are not available, {err}"))
+                .ok();
+        }
+        for limit in &mut limits {
+            if let LimitTarget::App(app_name) = &mut limit.target {
+                match db.get_alias(app_name) {
+                    Ok(Some(alias)) => {
+                        info!("The limit of {app_name} applies to its alias {alias}");
+                        *app_name = alias;
+                    }
+                    Ok(None) => {}
+                    Err(err) => error!("Failed to query the alias of {app_name}: {err}"),
+                }
+            }
+            if let Some(action) = window_action(limit.action.as_ref())
+                && !self.compositor_kind.supports(action)
+            {
+                warn!(
+                    "{} can't {action} windows, the limit of {} only notifies",
+                    self.compositor_kind,
+                    limit.name()
+                );
+            }
+        }
+        self.limits = limits;
+    }
+
+    pub fn set_categories(&mut self, categories: Vec<CategoryRule>) {
+        self.categories = categories;
+    }
+
+    /// Compare today's screentime to the limits and notify about newly reached thresholds.
+    /// The action of an exceeded limit is performed when it is exceeded and whenever focus
+    /// moves to a window counting towards it.
+    pub fn check(&mut self, db: &Database, focused_window: Option<&WindowInfo>) {
+        if self.limits.is_empty() {
+            return;
+        }
+
+        // limits on apps apply to their alias
+        let window = focused_window.map(|window| WindowInfo {
+            app_name: db
+                .get_alias(&window.app_name)
+                .inspect_err(|err| {
+                    error!("Failed to query the alias of {}: {err}", window.app_name)
+                })
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| window.app_name.clone()),
+            title: window.title.clone(),
+            id: window.id,
+        });
+        let previous_window = mem::replace(&mut self.window, window);
+
+        let now = Local::now();
+        if now.date_naive() != self.day {
+            self.day = now.date_naive();
+            self.notified.clear();
+        }
+
+        let (Some(start), Some(end)) = (
+            now.with_time(NaiveTime::MIN).earliest(),
+            NaiveTime::from_hms_opt(23, 59, 59).and_then(|time| now.with_time(time).latest()),
+        ) else {
+            return;
+        };
+        let (start, end) = (start.to_utc(), end.to_utc());
+
+        let apps = match db.get_screentime_in_range(start, end, &Activity::DEFAULT) {
+            Ok(apps) => apps,
+            Err(err) => {
+                error!("Failed to query today's screentime: {err}");
+                return;
+            }
+        };
+        let has_category_limits = self
+            .limits
+            .iter()
+            .any(|limit| matches!(limit.target, LimitTarget::Category(_)));
+        let categories = if has_category_limits {
+            db.get_category_screentime_in_range(start, end, &Activity::DEFAULT, &self.categories)
+                .inspect_err(|err| error!("Failed to query today's categories: {err}"))
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        let (notifications, actions) = self.update(&apps, &categories, &previous_window);
+        for notification in notifications {
+            info!("{}", notification.summary);
+            if let Some(notifier) = &self.notifier
+                && let Err(err) = notifier.notify(
+                    &notification.summary,
+                    &notification.body,
+                    notification.urgency,
+                )
+            {
+                warn!("Failed to send a notification: {err}");
+            }
+        }
+
+        for (limit, action) in actions {
+            let enforcement = Enforcement {
+                limit,
+                action,
+                window_id: self.window.as_ref().and_then(|window| window.id),
+            };
+            let enforcer = self
+                .enforcer
+                .get_or_insert_with(|| Enforcer::spawn(self.compositor_kind));
+            if enforcer.send(enforcement).is_err() {
+                error!("The limit actions can't be performed anymore");
+            }
+        }
+    }
+
+    /// Record the thresholds reached by today's screentime, returning the notifications of the
+    /// newly reached ones and the actions to perform on the focused window.
+    fn update(
+        &mut self,
+        apps: &[AppScreentime],
+        categories: &[CategoryScreentime],
+        previous_window: &Option<WindowInfo>,
+    ) -> (Vec<Notification>, Vec<(String, LimitAction)>) {
+        let applies_to = |limit: &Limit, window: &Option<WindowInfo>| {
+            window.as_ref().is_some_and(|window| {
+                limit.applies_to(&window.app_name, &window.title, &self.categories)
+            })
+        };
+
+        let mut notifications = Vec::new();
+        let mut actions = Vec::new();
+        for limit in &self.limits {
+            let usage = limit.usage(apps, categories);
+            let Some(threshold) = limit.threshold(usage) else {
+                continue;
+            };
+            let notified = self.notified.entry(limit.target.clone()).or_default();
+            let newly_reached = threshold > *notified;
+            *notified = threshold.max(*notified);
+
+            if let Some(action) = &limit.action
+                && threshold >= 100
+                && applies_to(limit, &self.window)
+                && (newly_reached || !applies_to(limit, previous_window))
+            {
+                actions.push((limit.name().to_owned(), action.clone()));
+            }
+            if !newly_reached {
+                continue;
+            }
+
+            let daily = format_millis_short(limit.daily.as_millis());
+            notifications.push(if threshold >= 100 {
+                Notification {
+                    summary: format!("Screentime limit of {} reached", limit.name()),
+                    body: format!("The daily limit of {daily} is used up."),
+                    urgency: Urgency::Critical,
+                }
+            } else {
+                Notification {
+                    summary: format!("Screentime limit of {} almost reached", limit.name()),
+                    body: format!(
+                        "{} of the daily limit of {daily} used.",
+                        format_millis_short(usage)
+                    ),
+                    urgency: Urgency::Normal,
+                }
+            });
+        }
+
+        (notifications, actions)
+    }
+}
+
+/// A notification about a limit reaching a threshold.
+struct Notification {
+    summary: String,
+    body: String,
+    urgency: Urgency,
+}
+
+/// The action of an exceeded limit on the window that counted towards it.
+struct Enforcement {
+    limit: String,
+    action: LimitAction,
+    window_id: Option<u64>,
+}
+
+/// Performs the actions of exceeded limits on its own thread, since they block on D-Bus, the
+/// compositor or a command.
+struct Enforcer {
+    compositor_kind: CompositorKind,
+    /// Connected on the first window action, separately from the watched connection
+    compositor: Option<Box<dyn Compositor>>,
+}
+
+impl Enforcer {
+    fn spawn(compositor_kind: CompositorKind) -> Sender<Enforcement> {
+        let (sender, receiver) = channel::<Enforcement>();
+        spawn(move || {
+            let mut enforcer = Enforcer {
+                compositor_kind,
+                compositor: None,
+            };
+            for enforcement in receiver {
+                let limit = &enforcement.limit;
+                info!(
+                    "Performing the action of the limit of {limit}: {:?}",
+                    enforcement.action
+                );
+                if let Err(err) = enforcer.perform(&enforcement) {
+                    error!("Failed to perform the action of the limit of {limit}: {err}");
+                }
+            }
+        });
+        sender
+    }
+
+    fn perform(&mut self, enforcement: &Enforcement) -> io::Result<()> {
+        match &enforcement.action {
+            LimitAction::Lock => session::lock_session(),
+            LimitAction::Command(command) => {
+                let mut child = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .env("WAYSTED_LIMIT", &enforcement.limit)
+                    .spawn()?;
+                // reap the command without blocking other actions
+                spawn(move || match child.wait() {
+                    Ok(status) if !status.success() => warn!("Limit command exited with {status}"),
+                    Ok(_) => {}
+                    Err(err) => error!("Failed to wait for the limit command: {err}"),
+                });
+                Ok(())
+            }
+            action @ (LimitAction::Close | LimitAction::Minimize) => {
+                let Some(action) = window_action(Some(action)) else {
+                    return Ok(());
+                };
+                if !self.compositor_kind.supports(action) {
+                    debug!("{} can't {action} windows", self.compositor_kind);
+                    return Ok(());
+                }
+                let Some(id) = enforcement.window_id else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!(
+                            "{} didn't report the id of the window",
+                            self.compositor_kind
+                        ),
+                    ));
+                };
+
+                let compositor = match &mut self.compositor {
+                    Some(compositor) => compositor,
+                    compositor => compositor.insert(get_compositor(self.compositor_kind)?),
+                };
+                let result = compositor.perform_action(id, action);
+                if result.is_err() {
+                    // reconnect on the next action, the connection may have dropped
+                    self.compositor = None;
+                }
+                result
+            }
+        }
+    }
+}
+
+fn window_action(action: Option<&LimitAction>) -> Option<WindowAction> {
+    match action? {
+        LimitAction::Close => Some(WindowAction::Close),
+        LimitAction::Minimize => Some(WindowAction::Minimize),
+        LimitAction::Lock | LimitAction::Command(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, time::Duration};
+
+    use super::*;
+
+    fn checker(limits: Vec<Limit>) -> LimitChecker {
+        LimitChecker {
+            limits,
+            categories: Vec::new(),
+            notifier: None,
+            compositor_kind: CompositorKind::Sway,
+            enforcer: None,
+            window: None,
+            day: Local::now().date_naive(),
+            notified: HashMap::new(),
+        }
+    }
+
+    fn limit(app: &str, warn_at: &[u8], action: Option<LimitAction>) -> Limit {
+        Limit {
+            target: LimitTarget::App(app.to_owned()),
+            daily: Duration::from_secs(60 * 60),
+            warn_at: warn_at.to_vec(),
+            action,
+        }
+    }
+
+    fn apps(app_name: &str, minutes: u128) -> Vec<AppScreentime> {
+        vec![AppScreentime {
+            id: 1,
+            app_name: app_name.to_owned(),
+            duration: minutes * 60 * 1000,
+            percentage: 100,
+            display_name: None,
+            icon: None,
+            categories: Vec::new(),
+        }]
+    }
+
+    #[test]
+    fn notifies_each_threshold_once() {
+        let mut checker = checker(vec![limit("firefox", &[50, 90], None)]);
+        let mut notify = |minutes| {
+            let (notifications, _) = checker.update(&apps("Firefox", minutes), &[], &None);
+            notifications
+                .into_iter()
+                .map(|notification| (notification.summary, notification.urgency))
+                .collect::<Vec<_>>()
+        };
+        let almost = || {
+            (
+                "Screentime limit of firefox almost reached".to_owned(),
+                Urgency::Normal,
+            )
+        };
+        let reached = || {
+            (
+                "Screentime limit of firefox reached".to_owned(),
+                Urgency::Critical,
+            )
+        };
+
+        assert_eq!(notify(10), []);
+        assert_eq!(notify(30), [almost()]);
+        assert_eq!(notify(40), []);
+        // both warnings were passed since the last check
+        assert_eq!(notify(55), [almost()]);
+        assert_eq!(notify(60), [reached()]);
+        assert_eq!(notify(90), []);
+        // e.g. after today's screentime was cleared
+        assert_eq!(notify(30), []);
+    }
+
+    #[test]
+    fn acts_while_a_window_of_the_exceeded_limit_is_focused() {
+        let mut checker = checker(vec![limit("firefox", &[], Some(LimitAction::Close))]);
+        let firefox = Some(WindowInfo {
+            title: "GitHub".to_owned(),
+            app_name: "firefox".to_owned(),
+            id: Some(4),
+        });
+        let foot = Some(WindowInfo {
+            title: "~".to_owned(),
+            app_name: "foot".to_owned(),
+            id: Some(5),
+        });
+        let mut act =
+            |window: &Option<WindowInfo>, previous_window: &Option<WindowInfo>, minutes| {
+                checker.window = window.clone();
+                let (_, actions) = checker.update(&apps("firefox", minutes), &[], previous_window);
+                actions
+            };
+        let close = || vec![("firefox".to_owned(), LimitAction::Close)];
+
+        assert_eq!(act(&firefox, &firefox, 59), []);
+        assert_eq!(act(&foot, &firefox, 60), []);
+        // once exceeded, only when focus moves to the app
+        assert_eq!(act(&firefox, &foot, 61), close());
+        assert_eq!(act(&firefox, &firefox, 62), []);
+        assert_eq!(act(&foot, &firefox, 63), []);
+        assert_eq!(act(&firefox, &None, 64), close());
+    }
+
+    #[test]
+    fn acts_when_exceeded_while_focused() {
+        let mut checker = checker(vec![limit("firefox", &[], Some(LimitAction::Lock))]);
+        checker.window = Some(WindowInfo {
+            title: "GitHub".to_owned(),
+            app_name: "firefox".to_owned(),
+            id: None,
+        });
+        let previous_window = checker.window.clone();
+
+        let (_, actions) = checker.update(&apps("firefox", 60), &[], &previous_window);
+        assert_eq!(actions, [("firefox".to_owned(), LimitAction::Lock)]);
+        let (_, actions) = checker.update(&apps("firefox", 61), &[], &previous_window);
+        assert_eq!(actions, []);
+    }
+
+    #[test]
+    fn applies_limits_to_aliases() {
+        let path = env::temp_dir().join(format!("waysted-{}-limit-aliases.db", process::id()));
+        let _ = fs::remove_file(&path);
+        let db = Database::new(&path, true).unwrap();
+        db.set_config_aliases(&HashMap::from([(
+            "org.mozilla.firefox".to_owned(),
+            "Firefox".to_owned(),
+        )]))
+        .unwrap();
+
+        let mut checker = checker(Vec::new());
+        checker.set_limits(
+            vec![
+                limit("org.mozilla.firefox", &[], None),
+                limit("foot", &[], None),
+            ],
+            &db,
+        );
+        let names: Vec<_> = checker.limits.iter().map(Limit::name).collect();
+        assert_eq!(names, ["Firefox", "foot"]);
+
+        drop(db);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,19 +1,73 @@
-use std::process::ExitCode;
+use std::{io, path::PathBuf, process::ExitCode};
 
+use clap::Parser;
 use log::{error, info};
+use waysted_core::{compositor::CompositorKind, config::Config};
 
 use crate::daemon::Daemon;
 
 mod daemon;
+mod limits;
+mod server;
+mod supervisor;
+mod tracker;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// Path to the config file, defaults to `$XDG_CONFIG_HOME/waysted/config.toml`
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// The compositor to track instead of detecting it: one of `niri`, `hyprland`, `sway`, `cosmic`, `kde`, `gnome` or `wlr`
+    #[arg(long)]
+    compositor: Option<CompositorKind>,
+
+    /// Seconds without input after which the user is considered idle and time stops being counted, 0 disables idle detection [default: 300]
+    #[arg(long)]
+    idle_timeout: Option<u64>,
+
+    /// Seconds between saving the time of the focused window, limits how much is lost on a crash [default: 60]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoint_interval: Option<u64>,
+}
+
+impl Cli {
+    /// Load the config, the command line options take precedence over it.
+    fn load_config(&self) -> io::Result<Config> {
+        let mut config = Config::load(self.config.as_deref())?;
+        if self.compositor.is_some() {
+            config.daemon.compositor = self.compositor;
+        }
+        if let Some(idle_timeout) = self.idle_timeout {
+            config.daemon.idle_timeout = idle_timeout;
+        }
+        if let Some(checkpoint_interval) = self.checkpoint_interval {
+            config.daemon.checkpoint_interval = checkpoint_interval;
+        }
+        Ok(config)
+    }
+}
 
 fn main() -> ExitCode {
+    let cli = Cli::parse();
+
     env_logger::init();
 
     info!("Starting waysted daemon...");
 
-    match Daemon::new() {
-        Ok(mut daemon) => {
-            if let Err(err) = daemon.start() {
+    let config = match cli.load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Failed to load config: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let config_path = cli.config.clone().unwrap_or_else(Config::default_path);
+    match Daemon::new(&config) {
+        Ok(daemon) => {
+            if let Err(err) = daemon.start(&config_path, || cli.load_config()) {
                 error!("{err}");
                 return ExitCode::FAILURE;
             }
@@ -0,0 +1,113 @@
+use std::{
+    fs,
+    io::{self, BufReader},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{Sender, channel},
+    thread::spawn,
+};
+
+use log::{debug, info, warn};
+use waysted_core::ipc::{self, Request, Response};
+
+use crate::daemon::Event;
+
+/// Control socket for the CLI and other clients, requests are answered by the event loop.
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Server {
+    pub fn start(sender: Sender<Event>) -> io::Result<Self> {
+        let path = ipc::socket_path()?;
+        let listener = bind(&path)?;
+        info!("Listening for requests on {}", path.display());
+
+        spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        spawn(move || {
+                            if let Err(err) = handle_client(stream, sender) {
+                                debug!("Client disconnected, {err}");
+                            }
+                        });
+                    }
+                    Err(err) => warn!("Failed to accept a client: {err}"),
+                }
+            }
+        });
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("Failed to remove {}: {err}", self.path.display());
+        }
+    }
+}
+
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("Another daemon is listening on {}", path.display()),
+                ));
+            }
+
+            // left behind by a daemon that did not exit cleanly
+            debug!("Removing stale socket {}", path.display());
+            fs::remove_file(path)?;
+            UnixListener::bind(path)
+        }
+        result => result,
+    }
+}
+
+fn handle_client(stream: UnixStream, sender: Sender<Event>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    loop {
+        let response = match ipc::read_message::<Request>(&mut reader) {
+            Ok(Some(Request::Subscribe)) => {
+                let (update_sender, updates) = channel();
+                if sender.send(Event::Subscribe(update_sender)).is_err() {
+                    return Ok(());
+                }
+
+                // the connection only streams updates from now on
+                ipc::write_message(&mut writer, &Response::Ok)?;
+                for update in updates {
+                    ipc::write_message(&mut writer, &update)?;
+                }
+                return Ok(());
+            }
+            Ok(Some(request)) => {
+                let (response_sender, response_receiver) = channel();
+                if sender
+                    .send(Event::Request(request, response_sender))
+                    .is_err()
+                {
+                    return Ok(());
+                }
+                response_receiver
+                    .recv()
+                    .unwrap_or_else(|_| Response::Error("The daemon is shutting down".to_owned()))
+            }
+            Ok(None) => return Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                Response::Error(format!("Invalid request, {err}"))
+            }
+            Err(err) => return Err(err),
+        };
+
+        ipc::write_message(&mut writer, &response)?;
+    }
+}
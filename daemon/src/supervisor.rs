@@ -0,0 +1,136 @@
+use std::{
+    mem,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info, warn};
+use waysted_core::compositor::{
+    Compositor, CompositorKind, StopHandle, WindowInfo, get_compositor,
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A watcher that stays connected for this long is considered healthy again and resets the backoff.
+const HEALTHY_CONNECTION: Duration = Duration::from_secs(60);
+
+/// What the supervisor thread is doing, to know how to stop it
+enum State {
+    Connecting,
+    /// Watching the focused window, with a handle if the watch can be stopped
+    Watching(Option<StopHandle>),
+}
+
+/// Watches the focused window on its own thread and reconnects to the compositor whenever its
+/// IPC drops, e.g. when the compositor is reloaded.
+pub struct Supervisor {
+    stopped: Arc<AtomicBool>,
+    state: Arc<Mutex<State>>,
+    thread: JoinHandle<()>,
+}
+
+impl Supervisor {
+    pub fn spawn(
+        kind: CompositorKind,
+        compositor: Box<dyn Compositor>,
+        sender: Sender<WindowInfo>,
+    ) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let state = Arc::new(Mutex::new(State::Connecting));
+        let thread = thread::spawn({
+            let stopped = stopped.clone();
+            let state = state.clone();
+            move || supervise(kind, compositor, sender, &stopped, &state)
+        });
+
+        Self {
+            stopped,
+            state,
+            thread,
+        }
+    }
+
+    /// Stop watching and wait for the compositor to be dropped, so backends can clean up. Backends
+    /// whose watch can't be stopped are left running, they have nothing to clean up.
+    pub fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        let state = mem::replace(&mut *self.state.lock().unwrap(), State::Connecting);
+        match state {
+            State::Watching(None) => return,
+            State::Watching(Some(stop_watch)) => stop_watch(),
+            // wakes up the thread if it is waiting to reconnect
+            State::Connecting => self.thread.thread().unpark(),
+        }
+
+        if self.thread.join().is_err() {
+            error!("The compositor watcher panicked");
+        }
+    }
+}
+
+/// Only returns once the receiver is gone or the supervisor is stopped.
+fn supervise(
+    kind: CompositorKind,
+    mut compositor: Box<dyn Compositor>,
+    sender: Sender<WindowInfo>,
+    stopped: &AtomicBool,
+    state: &Mutex<State>,
+) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        // resync the focused window since focus changes may have been missed while disconnected
+        match compositor.get_focused_window() {
+            Ok(window_info) => {
+                if sender.send(window_info).is_err() {
+                    return;
+                }
+            }
+            Err(err) => debug!("Could not resync the focused window: {err}"),
+        }
+
+        // checked after publishing the handle, so a stop either sees the handle or is seen here
+        *state.lock().unwrap() = State::Watching(compositor.stop_handle());
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let connected_at = Instant::now();
+        let result = compositor.watch_focused_window(sender.clone());
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        match result {
+            Ok(()) => warn!("The {kind} IPC connection was closed"),
+            Err(err) => error!("Lost the {kind} IPC connection: {err}"),
+        }
+
+        if connected_at.elapsed() >= HEALTHY_CONNECTION {
+            backoff = INITIAL_BACKOFF;
+        }
+
+        // release the old connection first, backends such as KWin own a bus name and clean up
+        // after themselves when dropped, which would otherwise conflict with the new instance
+        *state.lock().unwrap() = State::Connecting;
+        drop(compositor);
+        compositor = loop {
+            info!("Reconnecting to {kind} in {}s", backoff.as_secs());
+            thread::park_timeout(backoff);
+            if stopped.load(Ordering::SeqCst) {
+                return;
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+
+            match get_compositor(kind) {
+                Ok(compositor) => break compositor,
+                Err(err) => warn!("Failed to reconnect to {kind}: {err}"),
+            }
+        };
+        info!("Reconnected to {kind}");
+    }
+}
@@ -0,0 +1,588 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use log::{debug, error};
+use waysted_core::{
+    compositor::WindowInfo,
+    database::{Activity, AppScreentime, Database},
+    desktop::DesktopEntries,
+    ipc::{Status, Update},
+    rules::{self, IgnoreRule, PrivacyRule, TitleKey},
+};
+
+/// Spans shorter than this are not kept, e.g. while switching through windows or between the
+/// idle notifications with and without idle inhibitors, which are sent separately.
+const MIN_SPAN: Duration = Duration::from_secs(1);
+
+/// The time the focused window has been counted since, persisted as an open row.
+struct Span {
+    id: i64,
+    start_time: Instant,
+    start_timestamp: DateTime<Utc>,
+    activity: Activity,
+}
+
+/// Reasons for the focused window not to be counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Inactivity {
+    Locked,
+    Sleeping,
+    /// Another session is in the foreground
+    SessionInactive,
+    /// Requested by the user
+    Paused,
+}
+
+/// A pause requested by the user, recorded in the database.
+struct Pause {
+    id: i64,
+    until: Option<Instant>,
+}
+
+/// Attributes the time between focus changes to the focused window, except while inactive.
+pub struct Tracker {
+    db: Database,
+    ignore_rules: Vec<IgnoreRule>,
+    privacy_rules: Vec<PrivacyRule>,
+    /// Loaded once a privacy rule hashes titles
+    title_key: Option<TitleKey>,
+    desktop_entries: DesktopEntries,
+    /// The focused window as reported by the compositor
+    window: Option<WindowInfo>,
+    /// The focused window as recorded, after applying the rules
+    focused_window: Option<WindowInfo>,
+    span: Option<Span>,
+    activity: Activity,
+    inactivity: HashSet<Inactivity>,
+    pause: Option<Pause>,
+    subscribers: Vec<Sender<Update>>,
+}
+
+impl Tracker {
+    pub fn new(
+        db: Database,
+        ignore_rules: Vec<IgnoreRule>,
+        privacy_rules: Vec<PrivacyRule>,
+    ) -> Self {
+        let mut tracker = Self {
+            db,
+            ignore_rules,
+            privacy_rules,
+            title_key: None,
+            desktop_entries: DesktopEntries::load(),
+            window: None,
+            focused_window: None,
+            span: None,
+            activity: Activity::Active,
+            inactivity: HashSet::new(),
+            pause: None,
+            subscribers: Vec::new(),
+        };
+        tracker.load_title_key();
+        tracker
+    }
+
+    pub fn focus_changed(&mut self, window_info: WindowInfo) {
+        // the window is sent again after reconnecting to the compositor, keep its span
+        if self.window.as_ref() != Some(&window_info) {
+            self.focus(window_info);
+        }
+    }
+
+    /// Start a new span for the window, ending the span of the previous one.
+    fn focus(&mut self, window_info: WindowInfo) {
+        let now = Instant::now();
+        self.end_span(now);
+        self.window = Some(window_info.clone());
+        self.focused_window = self.recorded_window(window_info);
+        if self.inactivity.is_empty() {
+            self.start_span(now);
+        }
+
+        self.publish(Update::Focus(self.focused_window.clone()));
+        self.publish_totals();
+    }
+
+    /// Apply new rules, starting a new span if the focused window is now recorded differently.
+    pub fn set_rules(&mut self, ignore_rules: Vec<IgnoreRule>, privacy_rules: Vec<PrivacyRule>) {
+        self.ignore_rules = ignore_rules;
+        self.privacy_rules = privacy_rules;
+        self.load_title_key();
+        let Some(window) = self.window.clone() else {
+            return;
+        };
+
+        if self.recorded_window(window.clone()) != self.focused_window {
+            self.focus(window);
+        }
+    }
+
+    /// Load the key of the title hashes once a rule needs it, hashed titles are dropped without.
+    fn load_title_key(&mut self) {
+        if self.title_key.is_some() || !PrivacyRule::any_hashed(&self.privacy_rules) {
+            return;
+        }
+
+        let path = TitleKey::path(&self.db.get_path());
+        match TitleKey::load_or_create(&path) {
+            Ok(key) => self.title_key = Some(key),
+            Err(err) => error!(
+                "Failed to load the title hash key {}, titles to hash are not stored: {err}",
+                path.display()
+            ),
+        }
+    }
+
+    /// The focused window as recorded, None if it is ignored.
+    pub fn focused_window(&self) -> Option<&WindowInfo> {
+        self.focused_window.as_ref()
+    }
+
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Pick up apps installed since the desktop entries were loaded.
+    pub fn reload_desktop_entries(&mut self) {
+        self.desktop_entries = DesktopEntries::load();
+    }
+
+    /// Screentime per app including the focused window.
+    pub fn screentime(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        activities: &[Activity],
+    ) -> Result<Vec<AppScreentime>, Box<dyn Error>> {
+        self.checkpoint();
+        let mut screentime = self.db.get_screentime_in_range(start, end, activities)?;
+        self.desktop_entries.annotate(&mut screentime);
+        Ok(screentime)
+    }
+
+    /// The window as it is recorded, None if it is ignored.
+    fn recorded_window(&self, window: WindowInfo) -> Option<WindowInfo> {
+        rules::filter_window(&self.ignore_rules, window)
+            .map(|window| rules::redact_title(&self.privacy_rules, self.title_key.as_ref(), window))
+    }
+
+    /// Attribute the time from `since` to the new activity.
+    pub fn activity_changed(&mut self, activity: Activity, since: Instant) {
+        if activity == self.activity {
+            return;
+        }
+
+        debug!("Activity changed to {activity}");
+        self.activity = activity;
+        self.publish(Update::Activity(activity));
+        if let Some(span) = &self.span {
+            // the previous activity may have started later than `since`
+            let since = since.max(span.start_time);
+            self.end_span(since);
+            self.start_span(since);
+        }
+    }
+
+    /// Stop counting the focused window from `since` until every inactivity has ended.
+    pub fn inactive(&mut self, inactivity: Inactivity, since: Instant) {
+        debug!("Inactive: {inactivity:?}");
+        self.end_span(since);
+        self.inactivity.insert(inactivity);
+    }
+
+    pub fn active(&mut self, inactivity: Inactivity) {
+        if self.inactivity.remove(&inactivity) {
+            debug!("No longer inactive: {inactivity:?}");
+        }
+        if self.inactivity.is_empty() && self.span.is_none() {
+            self.start_span(Instant::now());
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            window: self.focused_window.clone(),
+            activity: self.activity,
+            elapsed: self
+                .span
+                .as_ref()
+                .map_or(0, |span| span.start_time.elapsed().as_millis()),
+            paused: self.pause.is_some(),
+            resumes_in: self
+                .pause
+                .as_ref()
+                .and_then(|pause| pause.until)
+                .map(|until| until.saturating_duration_since(Instant::now()).as_millis()),
+        }
+    }
+
+    /// Stop counting until resumed or until `duration` has passed.
+    pub fn pause(&mut self, duration: Option<Duration>) {
+        // durations too long to represent pause until resumed
+        let until = duration.and_then(|duration| Instant::now().checked_add(duration));
+        match &mut self.pause {
+            // extend or shorten the current pause
+            Some(pause) => pause.until = until,
+            None => {
+                let id = self.db.log_pause(Utc::now());
+                self.pause = Some(Pause { id, until });
+                self.inactive(Inactivity::Paused, Instant::now());
+                self.publish(Update::Paused(true));
+            }
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(pause) = self.pause.take() {
+            self.db.end_pause(pause.id, Utc::now());
+            self.active(Inactivity::Paused);
+            self.publish(Update::Paused(false));
+        }
+    }
+
+    /// Time left until a timed pause ends.
+    pub fn pause_remaining(&self) -> Option<Duration> {
+        let until = self.pause.as_ref()?.until?;
+        Some(until.saturating_duration_since(Instant::now()))
+    }
+
+    /// Persist the span of the focused window before exiting.
+    pub fn finish(&mut self) {
+        self.end_span(Instant::now());
+        if let Some(pause) = self.pause.take() {
+            self.db.end_pause(pause.id, Utc::now());
+        }
+    }
+
+    /// Update the open row so at most the checkpoint interval is lost on a crash.
+    pub fn checkpoint(&self) {
+        if let Some(span) = &self.span {
+            let duration = span.start_time.elapsed();
+            self.db
+                .update_focus_duration(span.id, duration, span.start_timestamp + duration);
+        }
+    }
+
+    /// Send the current state to a new subscriber and keep it updated.
+    pub fn subscribe(&mut self, subscriber: Sender<Update>) {
+        let mut updates = vec![
+            Update::Activity(self.activity),
+            Update::Paused(self.pause.is_some()),
+            Update::Focus(self.focused_window.clone()),
+        ];
+        updates.extend(self.totals().map(Update::Totals));
+
+        if updates
+            .into_iter()
+            .all(|update| subscriber.send(update).is_ok())
+        {
+            self.subscribers.push(subscriber);
+        }
+    }
+
+    /// Send today's totals to the subscribers.
+    pub fn publish_totals(&mut self) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+
+        if let Some(totals) = self.totals() {
+            self.publish(Update::Totals(totals));
+        }
+    }
+
+    fn publish(&mut self, update: Update) {
+        // subscribers are dropped once their connection is closed
+        self.subscribers
+            .retain(|subscriber| subscriber.send(update.clone()).is_ok());
+    }
+
+    /// Today's screentime per app including the focused window.
+    fn totals(&self) -> Option<Vec<AppScreentime>> {
+        let today = Local::now();
+        let start = today.with_time(NaiveTime::MIN).earliest()?;
+        let end = today
+            .with_time(NaiveTime::from_hms_opt(23, 59, 59)?)
+            .latest()?;
+        self.screentime(start.to_utc(), end.to_utc(), &Activity::DEFAULT)
+            .inspect_err(|err| error!("Failed to query today's screentime: {err}"))
+            .ok()
+    }
+
+    fn start_span(&mut self, start_time: Instant) {
+        if let Some(window_info) = &self.focused_window {
+            let start_timestamp = Utc::now() - start_time.elapsed();
+            let id = self.db.log_focus_duration(
+                window_info.clone(),
+                Duration::ZERO,
+                start_timestamp,
+                start_timestamp,
+                self.activity,
+            );
+
+            self.span = Some(Span {
+                id,
+                start_time,
+                start_timestamp,
+                activity: self.activity,
+            });
+        }
+    }
+
+    fn end_span(&mut self, end_time: Instant) {
+        let (Some(span), Some(window_info)) = (self.span.take(), &self.focused_window) else {
+            return;
+        };
+
+        let duration = end_time.saturating_duration_since(span.start_time);
+        if duration < MIN_SPAN {
+            self.db.remove_focus_duration(span.id);
+            return;
+        }
+
+        debug!(
+            "{} - {} focused for {}ms ({})",
+            window_info.app_name,
+            window_info.title,
+            duration.as_millis(),
+            span.activity
+        );
+
+        self.db
+            .update_focus_duration(span.id, duration, span.start_timestamp + duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use super::*;
+
+    /// A tracker over a database file of its own, removed once dropped.
+    struct TestTracker {
+        tracker: Tracker,
+        path: PathBuf,
+    }
+
+    impl TestTracker {
+        fn new(name: &str, ignore_rules: Vec<IgnoreRule>) -> Self {
+            let path = env::temp_dir().join(format!("waysted-{}-{name}.db", process::id()));
+            let _ = fs::remove_file(&path);
+            let db = Database::new(&path, true).unwrap();
+            Self {
+                tracker: Tracker::new(db, ignore_rules, Vec::new()),
+                path,
+            }
+        }
+
+        /// Pretend `duration` has passed, by moving everything recorded so far back in time.
+        fn elapse(&mut self, duration: Duration) {
+            if let Some(span) = &mut self.tracker.span {
+                span.start_time -= duration;
+                span.start_timestamp -= duration;
+            }
+            self.connect()
+                .execute(
+                    "UPDATE screentime SET start_timestamp = start_timestamp - ?1,
+                     end_timestamp = end_timestamp - ?1",
+                    (duration.as_millis() as i64,),
+                )
+                .unwrap();
+        }
+
+        fn connect(&self) -> rusqlite::Connection {
+            rusqlite::Connection::open(&self.path).unwrap()
+        }
+
+        /// The recorded apps and their durations in seconds, oldest first.
+        fn logs(&self) -> Vec<(String, u64)> {
+            let end = Utc::now() + Duration::from_secs(24 * 60 * 60);
+            let mut logs: Vec<_> = self
+                .tracker
+                .database()
+                .get_logs(DateTime::UNIX_EPOCH, end, &Activity::ALL)
+                .unwrap()
+                .into_iter()
+                .map(|instance| (instance.app_name, (instance.duration / 1000) as u64))
+                .collect();
+            logs.reverse();
+            logs
+        }
+
+        fn row_count(&self, table: &str) -> usize {
+            self.connect()
+                .query_row(&format!("SELECT COUNT(*) FROM {table}"), (), |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        }
+    }
+
+    impl Drop for TestTracker {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn window(app_name: &str) -> WindowInfo {
+        WindowInfo {
+            title: format!("{app_name} window"),
+            app_name: app_name.to_owned(),
+            id: None,
+        }
+    }
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn focus_change_closes_the_previous_span() {
+        let mut test = TestTracker::new("focus", Vec::new());
+        test.tracker.focus_changed(window("foot"));
+        test.elapse(MINUTE);
+        test.tracker.focus_changed(window("firefox"));
+        test.elapse(2 * MINUTE);
+        test.tracker.finish();
+
+        assert_eq!(
+            test.logs(),
+            [("foot".to_owned(), 60), ("firefox".to_owned(), 120)]
+        );
+    }
+
+    #[test]
+    fn refocusing_the_same_window_keeps_the_span() {
+        let mut test = TestTracker::new("refocus", Vec::new());
+        test.tracker.focus_changed(window("foot"));
+        test.elapse(MINUTE);
+        test.tracker.focus_changed(window("foot"));
+        test.elapse(MINUTE);
+        test.tracker.finish();
+
+        assert_eq!(test.logs(), [("foot".to_owned(), 120)]);
+    }
+
+    #[test]
+    fn short_spans_are_not_kept() {
+        let mut test = TestTracker::new("short", Vec::new());
+        test.tracker.focus_changed(window("foot"));
+        test.tracker.focus_changed(window("firefox"));
+        test.tracker.finish();
+
+        assert_eq!(test.row_count("screentime"), 0);
+    }
+
+    #[test]
+    fn checkpoint_updates_the_open_row() {
+        let mut test = TestTracker::new("checkpoint", Vec::new());
+        test.tracker.focus_changed(window("foot"));
+        test.elapse(MINUTE);
+        test.tracker.checkpoint();
+        assert_eq!(test.logs(), [("foot".to_owned(), 60)]);
+
+        test.elapse(MINUTE);
+        test.tracker.checkpoint();
+        assert_eq!(test.logs(), [("foot".to_owned(), 120)]);
+        assert_eq!(test.row_count("screentime"), 1);
+    }
+
+    #[test]
+    fn pause_ends_the_span_until_resumed() {
+        let mut test = TestTracker::new("pause", Vec::new());
+        test.tracker.focus_changed(window("foot"));
+        test.elapse(MINUTE);
+
+        test.tracker.pause(None);
+        assert!(test.tracker.span.is_none());
+        assert!(test.tracker.status().paused);
+        assert_eq!(test.row_count("pauses"), 1);
+        assert_eq!(test.logs(), [("foot".to_owned(), 60)]);
+
+        // focus changes while paused aren't counted
+        test.tracker.focus_changed(window("firefox"));
+        assert!(test.tracker.span.is_none());
+
+        test.tracker.resume();
+        test.elapse(MINUTE);
+        test.tracker.finish();
+        assert_eq!(
+            test.logs(),
+            [("foot".to_owned(), 60), ("firefox".to_owned(), 60)]
+        );
+        assert_eq!(test.row_count("pauses"), 1);
+    }
+
+    #[test]
+    fn timed_pauses_end() {
+        let mut test = TestTracker::new("timed-pause", Vec::new());
+        test.tracker.pause(Some(MINUTE));
+        assert!(test.tracker.pause_remaining().unwrap() <= MINUTE);
+
+        test.tracker.pause(Some(Duration::ZERO));
+        assert_eq!(test.tracker.pause_remaining(), Some(Duration::ZERO));
+        assert_eq!(test.row_count("pauses"), 1);
+    }
+
+    #[test]
+    fn inactivity_ends_the_span_from_its_start() {
+        let mut test = TestTracker::new("inactive", Vec::new());
+        test.tracker.focus_changed(window("foot"));
+        test.elapse(2 * MINUTE);
+
+        test.tracker
+            .inactive(Inactivity::Locked, Instant::now() - MINUTE);
+        test.tracker.inactive(Inactivity::Sleeping, Instant::now());
+        test.tracker.active(Inactivity::Locked);
+        assert!(test.tracker.span.is_none());
+
+        test.tracker.active(Inactivity::Sleeping);
+        test.elapse(MINUTE);
+        test.tracker.finish();
+        assert_eq!(
+            test.logs(),
+            [("foot".to_owned(), 60), ("foot".to_owned(), 60)]
+        );
+    }
+
+    #[test]
+    fn activity_changes_split_the_span() {
+        let mut test = TestTracker::new("activity", Vec::new());
+        test.tracker.focus_changed(window("foot"));
+        test.elapse(3 * MINUTE);
+
+        test.tracker
+            .activity_changed(Activity::Idle, Instant::now() - MINUTE);
+        test.tracker.finish();
+
+        let activities: Vec<_> = test
+            .tracker
+            .database()
+            .get_logs(DateTime::UNIX_EPOCH, Utc::now(), &Activity::ALL)
+            .unwrap()
+            .into_iter()
+            .map(|instance| (instance.activity, instance.duration / 1000))
+            .collect();
+        assert_eq!(activities, [(Activity::Idle, 60), (Activity::Active, 120)]);
+    }
+
+    #[test]
+    fn ignored_windows_are_not_recorded() {
+        let ignore_rules = vec![IgnoreRule {
+            app: Some("org.keepassxc.KeePassXC".to_owned()),
+            ..Default::default()
+        }];
+        let mut test = TestTracker::new("ignore", ignore_rules);
+        test.tracker
+            .focus_changed(window("org.keepassxc.KeePassXC"));
+        assert!(test.tracker.span.is_none());
+        assert_eq!(test.tracker.focused_window(), None);
+        test.tracker.finish();
+
+        assert_eq!(test.row_count("screentime"), 0);
+    }
+}
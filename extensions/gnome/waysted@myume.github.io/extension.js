@@ -0,0 +1,92 @@
+// Exposes the focused window on the session bus for the waysted daemon,
+// see core/src/compositor/gnome.rs.
+
+import Gio from "gi://Gio";
+import GLib from "gi://GLib";
+import { Extension } from "resource:///org/gnome/shell/extensions/extension.js";
+
+const SERVICE = "io.github.myume.Waysted.Gnome";
+const PATH = "/io/github/myume/Waysted/Gnome";
+
+const INTERFACE = `
+<node>
+  <interface name="io.github.myume.Waysted.Gnome">
+    <method name="GetFocusedWindow">
+      <arg type="s" direction="out" name="wm_class"/>
+      <arg type="s" direction="out" name="title"/>
+    </method>
+    <signal name="FocusChanged">
+      <arg type="s" name="wm_class"/>
+      <arg type="s" name="title"/>
+    </signal>
+  </interface>
+</node>`;
+
+export default class WaystedExtension extends Extension {
+  enable() {
+    this._window = null;
+    this._titleChangedId = 0;
+
+    this._dbus = Gio.DBusExportedObject.wrapJSObject(INTERFACE, this);
+    this._dbus.export(Gio.DBus.session, PATH);
+    this._nameId = Gio.bus_own_name_on_connection(
+      Gio.DBus.session,
+      SERVICE,
+      Gio.BusNameOwnerFlags.NONE,
+      null,
+      null,
+    );
+
+    this._focusChangedId = global.display.connect("notify::focus-window", () =>
+      this._onFocusChanged(),
+    );
+    this._onFocusChanged();
+  }
+
+  disable() {
+    global.display.disconnect(this._focusChangedId);
+    this._trackWindow(null);
+
+    Gio.bus_unown_name(this._nameId);
+    this._dbus.unexport();
+    this._dbus = null;
+  }
+
+  GetFocusedWindow() {
+    return this._describe(global.display.focus_window);
+  }
+
+  _describe(window) {
+    return [window?.get_wm_class() ?? "", window?.get_title() ?? ""];
+  }
+
+  _trackWindow(window) {
+    if (this._window && this._titleChangedId) {
+      this._window.disconnect(this._titleChangedId);
+    }
+
+    this._window = window;
+    this._titleChangedId = window?.connect("notify::title", () => this._emit()) ?? 0;
+  }
+
+  _onFocusChanged() {
+    const window = global.display.focus_window;
+    if (window === this._window) {
+      return;
+    }
+
+    this._trackWindow(window);
+    this._emit();
+  }
+
+  _emit() {
+    if (!this._window) {
+      return;
+    }
+
+    this._dbus.emit_signal(
+      "FocusChanged",
+      new GLib.Variant("(ss)", this._describe(this._window)),
+    );
+  }
+}